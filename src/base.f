@@ -1,36 +1,155 @@
 ( Standard subroutines to copy Piet functionality )
+( Each command is a word that behaves like the interpreter's Command::execute: a command )
+( that needs more values than the stack holds does nothing, and so do division by zero )
+( and invalid rolls. Arithmetic wraps around at the cell size. Input is read from gforth's )
+( STDIN, a byte at a time. )
 
-: NOT  ( n -- 0 | 1 )
-  DUP 0= IF DROP 1 ELSE DROP 0 THEN ;
+: PIET-POP ( n -- )
+    DEPTH 0= IF EXIT THEN
+    DROP ;
 
-: GREATER ( n1 n2 -- 0 | 1 )
-  > if 1 else 0 then ;
+: PIET-ADD ( n1 n2 -- n3 )
+    DEPTH 2 < IF EXIT THEN
+    + ;
 
-: PIET_ROLL ( depth rolls -- )
-    2dup 0< if
-        ." Invalid depth" cr
-        abort
-    then
+: PIET-SUBTRACT ( n1 n2 -- n3 )
+    DEPTH 2 < IF EXIT THEN
+    - ;
 
-    2dup 0= if
-        2drop 2drop
-        exit
-    then
+: PIET-MULTIPLY ( n1 n2 -- n3 )
+    DEPTH 2 < IF EXIT THEN
+    * ;
 
-    >r >r
-    2>r 2>r
-    2dup mod swap
+( SM/REM truncates like the interpreter's divide and mod. It can't divide MIN-INT by -1, )
+( so dividing by -1 negates, wrapping around, and the remainder is always 0. )
+: PIET-DIVIDE ( n1 n2 -- n3 )
+    DEPTH 2 < IF EXIT THEN
+    DUP 0= IF EXIT THEN
+    DUP -1 = IF DROP NEGATE EXIT THEN
+    >R S>D R> SM/REM NIP ;
 
-    begin
-        2dup 0>
-    while
-        2dup r@ +
-        r@ 1- r> swap r> swap pick swap pick swap !
-        1- repeat
-    drop drop
-    2r> 2r> drop drop ;
+: PIET-MOD ( n1 n2 -- n3 )
+    DEPTH 2 < IF EXIT THEN
+    DUP 0= IF EXIT THEN
+    DUP -1 = IF 2DROP 0 EXIT THEN
+    >R S>D R> SM/REM DROP ;
+
+: PIET-NOT ( n -- 0 | 1 )
+    DEPTH 0= IF EXIT THEN
+    0= 1 AND ;
+
+: PIET-GREATER ( n1 n2 -- 0 | 1 )
+    DEPTH 2 < IF EXIT THEN
+    > 1 AND ;
+
+( The DP as clockwise turns from pointing right, and the CC as 0 for left, 1 for right. )
+VARIABLE PIET-DP
+VARIABLE PIET-CC
+
+: PIET-POINTER ( n -- )
+    DEPTH 0= IF EXIT THEN
+    S>D 4 FM/MOD DROP PIET-DP @ + 4 MOD PIET-DP ! ;
+
+: PIET-SWITCH ( n -- )
+    DEPTH 0= IF EXIT THEN
+    S>D 2 FM/MOD DROP PIET-CC @ XOR PIET-CC ! ;
+
+: PIET-DUPLICATE ( n -- n n )
+    DEPTH 0= IF EXIT THEN
+    DUP ;
+
+( Buries the top value of the top depth values rolls times; negative rolls go the other )
+( way. A negative depth or one deeper than the stack pops the arguments and does nothing. )
+VARIABLE PIET-ROLL-DEPTH
+
+: PIET-ROLL ( depth rolls -- )
+    DEPTH 2 < IF EXIT THEN
+    OVER 0< IF 2DROP EXIT THEN
+    OVER 0= IF 2DROP EXIT THEN
+    OVER DEPTH 3 - > IF 2DROP EXIT THEN
+    S>D 2 PICK FM/MOD DROP
+    OVER SWAP -
+    SWAP 1- PIET-ROLL-DEPTH !
+    0 ?DO PIET-ROLL-DEPTH @ ROLL LOOP ;
+
+( A byte of input that was read ahead, or -1. )
+VARIABLE PIET-PENDING
+-1 PIET-PENDING !
+
+: PIET-READ-BYTE ( -- byte | -1 )
+    PIET-PENDING @ DUP 0< 0= IF -1 PIET-PENDING ! EXIT THEN DROP
+    PAD 1 STDIN READ-FILE THROW 0= IF -1 EXIT THEN
+    PAD C@ ;
+
+: PIET-UNREAD ( byte | -1 -- )
+    DUP 0< IF DROP EXIT THEN
+    PIET-PENDING ! ;
+
+: PIET-SPACE? ( c -- flag )
+    DUP 32 = OVER 9 = OR OVER 10 = OR OVER 12 = OR SWAP 13 = OR ;
+
+: PIET-DIGIT? ( c -- flag )
+    [CHAR] 0 [CHAR] 9 1+ WITHIN ;
+
+( Reads a decimal number with an optional sign after skipping whitespace, and leaves the )
+( character after it in the input. Pushes nothing at the end of the input or if the )
+( input isn't a number. )
+VARIABLE PIET-SIGN
+VARIABLE PIET-DIGITS
+VARIABLE PIET-VALID
+
+: PIET-IN-NUMBER ( -- n | )
+    BEGIN PIET-READ-BYTE DUP PIET-SPACE? WHILE DROP REPEAT
+    DUP 0< IF DROP EXIT THEN
+    1 PIET-SIGN ! 0 PIET-DIGITS ! TRUE PIET-VALID !
+    DUP [CHAR] - = IF DROP -1 PIET-SIGN ! 0 ELSE
+    DUP [CHAR] + = IF DROP 0 ELSE
+    DUP PIET-DIGIT? IF [CHAR] 0 - 1 PIET-DIGITS ! ELSE
+    DROP FALSE PIET-VALID ! 0 THEN THEN THEN
+    BEGIN PIET-READ-BYTE DUP PIET-DIGIT? WHILE
+        [CHAR] 0 - SWAP 10 * + 1 PIET-DIGITS +!
+    REPEAT
+    PIET-UNREAD
+    PIET-VALID @ 0= PIET-DIGITS @ 0= OR IF DROP EXIT THEN
+    PIET-SIGN @ * ;
+
+( Reads a UTF-8 encoded character and pushes its code point, or the value of its first )
+( byte if it isn't valid UTF-8. Pushes nothing at the end of the input. )
+VARIABLE PIET-FIRST
+
+: PIET-IN-CHAR ( -- c | )
+    PIET-READ-BYTE DUP 0< IF DROP EXIT THEN
+    DUP PIET-FIRST !
+    DUP 192 224 WITHIN IF 31 AND 1 128 ELSE
+    DUP 224 240 WITHIN IF 15 AND 2 2048 ELSE
+    DUP 240 248 WITHIN IF 7 AND 3 65536 ELSE
+    EXIT THEN THEN THEN
+    >R TRUE PIET-VALID !
+    0 ?DO
+        PIET-READ-BYTE DUP 0< IF DROP FALSE PIET-VALID ! LEAVE THEN
+        DUP 192 AND 128 <> IF FALSE PIET-VALID ! THEN
+        63 AND SWAP 6 LSHIFT OR
+    LOOP
+    R> OVER > IF FALSE PIET-VALID ! THEN
+    DUP 55296 57344 WITHIN IF FALSE PIET-VALID ! THEN
+    DUP 1114111 > IF FALSE PIET-VALID ! THEN
+    PIET-VALID @ 0= IF DROP PIET-FIRST @ THEN ;
+
+: PIET-OUT-NUMBER ( n -- )
+    DEPTH 0= IF EXIT THEN
+    0 .R ;
+
+( Writes a code point as UTF-8. Values that aren't code points are popped and ignored. )
+: PIET-OUT-CHAR ( c -- )
+    DEPTH 0= IF EXIT THEN
+    DUP 0< OVER 1114111 > OR OVER 55296 57344 WITHIN OR IF DROP EXIT THEN
+    DUP 128 < IF EMIT EXIT THEN
+    DUP 2048 < IF DUP 6 RSHIFT 192 OR EMIT ELSE
+    DUP 65536 < IF DUP 12 RSHIFT 224 OR EMIT ELSE
+    DUP 18 RSHIFT 240 OR EMIT DUP 12 RSHIFT 63 AND 128 OR EMIT THEN
+    DUP 6 RSHIFT 63 AND 128 OR EMIT THEN
+    63 AND 128 OR EMIT ;
 
 
 ( THE FOLLOWING CODE WAS GENERATED BY PIET TOOL )
 ( DO NOT EDIT )
-
@@ -0,0 +1,109 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::command::Command;
+
+/// A condition that stops a running program. Breakpoints are checked after every step.
+///
+/// Breakpoints can be written as text (see the [`FromStr`] implementation):
+///
+/// | Text | Breakpoint |
+/// | --- | --- |
+/// | `x,y` | `Codel((x, y))` |
+/// | `block x,y` | `Block((x, y))` |
+/// | `command out_char` | `Command(Command::OutChar)` |
+/// | `depth>n`, `depth<n` | `DepthAbove(n)`, `DepthBelow(n)` |
+/// | `top=v` | `TopEquals(v)` |
+/// | `pointer` | `PointerChange` |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Stop when the interpreter arrives on this codel.
+    Codel((i32, i32)),
+    /// Stop when the interpreter enters the color block containing this codel.
+    Block((i32, i32)),
+    /// Stop after this command has been executed.
+    Command(Command),
+    /// Stop when a command takes the stack from at most this many values to more.
+    DepthAbove(usize),
+    /// Stop when a command takes the stack from at least this many values to fewer.
+    DepthBelow(usize),
+    /// Stop when a command leaves this value on top of the stack.
+    TopEquals(i64),
+    /// Stop when a `Pointer` or `Switch` command changes the DP or CC.
+    PointerChange,
+}
+
+/// Why a running program stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The requested number of steps was taken, or the step limit was reached.
+    Stepped,
+    /// The breakpoint with this number was hit.
+    Breakpoint(usize),
+    /// The program terminated.
+    Terminated,
+    /// Stepping back reached the earliest recorded state.
+    HistoryStart,
+}
+
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.split_whitespace().collect::<String>();
+        if let Some(codel) = text.strip_prefix("block") {
+            return Ok(Breakpoint::Block(parse_codel(codel)?));
+        }
+        if let Some(command) = text.strip_prefix("command") {
+            return Ok(Breakpoint::Command(command.parse()?));
+        }
+        if let Some(depth) = text.strip_prefix("depth>") {
+            return Ok(Breakpoint::DepthAbove(parse_number(depth)?));
+        }
+        if let Some(depth) = text.strip_prefix("depth<") {
+            return Ok(Breakpoint::DepthBelow(parse_number(depth)?));
+        }
+        if let Some(value) = text.strip_prefix("top") {
+            let value = value.trim_start_matches('=');
+            return value
+                .parse()
+                .map(Breakpoint::TopEquals)
+                .map_err(|_| format!("{:?} is not a valid value", value));
+        }
+        if text == "pointer" {
+            return Ok(Breakpoint::PointerChange);
+        }
+        if text.contains(',') {
+            return Ok(Breakpoint::Codel(parse_codel(&text)?));
+        }
+        Err(format!("unknown breakpoint {:?}", s))
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Codel((x, y)) => write!(f, "{},{}", x, y),
+            Breakpoint::Block((x, y)) => write!(f, "block {},{}", x, y),
+            Breakpoint::Command(command) => write!(f, "command {:?}", command),
+            Breakpoint::DepthAbove(depth) => write!(f, "depth>{}", depth),
+            Breakpoint::DepthBelow(depth) => write!(f, "depth<{}", depth),
+            Breakpoint::TopEquals(value) => write!(f, "top={}", value),
+            Breakpoint::PointerChange => write!(f, "pointer"),
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Result<usize, String> {
+    text.parse()
+        .map_err(|_| format!("{:?} is not a valid count", text))
+}
+
+// Parses a codel position written as `x,y`.
+fn parse_codel(text: &str) -> Result<(i32, i32), String> {
+    let invalid = || format!("{:?} is not a codel position, expected x,y", text);
+    let (x, y) = text.split_once(',').ok_or_else(invalid)?;
+    let x = x.trim().parse().map_err(|_| invalid())?;
+    let y = y.trim().parse().map_err(|_| invalid())?;
+    Ok((x, y))
+}
@@ -0,0 +1,260 @@
+//! The static control flow of a program: every (block, DP, CC) state the interpreter can
+//! reach from the start, and where it goes from each, worked out without running it.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{
+    color::ColorName,
+    command::Command,
+    error::{PietError, Result},
+    graph::{BlockGraph, BlockId},
+    interpreter::{CodelChooser, Direction},
+    loader::Grid,
+};
+
+/// Where the interpreter is between two steps. Which codel of the block it is on doesn't
+/// matter: the way out of a block only depends on the DP and CC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct State {
+    pub block: BlockId,
+    pub direction_pointer: Direction,
+    pub codel_chooser: CodelChooser,
+}
+
+/// A move out of a state into the next block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edge {
+    /// The command executed on the way, or `None` for moves through white.
+    pub command: Option<Command>,
+    /// The size of the block that is left, which is what `Push` pushes.
+    pub value: usize,
+    /// The state the move ends in, before the command has run. `Pointer` and `Switch` can
+    /// still change its DP or CC.
+    pub to: State,
+    /// The nodes the program can continue in. For `Pointer`, the node after turning the
+    /// DP clockwise `k` times is `successors[k]`; for `Switch`, `successors[1]` is the node
+    /// with the CC toggled. Every other move has a single successor.
+    pub successors: Vec<usize>,
+}
+
+/// A reachable state and the move out of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub state: State,
+    /// `None` if the interpreter can't get out and the program terminates here.
+    pub edge: Option<Edge>,
+}
+
+/// Every state a program can reach from the start, as a graph of [`Node`]s.
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    nodes: Vec<Node>,
+    entry: Option<usize>,
+    index: HashMap<State, usize>,
+}
+
+impl ControlFlowGraph {
+    /// Explores every state reachable from the start of the program. All outcomes of
+    /// `Pointer` and `Switch` are followed, since their effect depends on the stack.
+    pub fn build(grid: &Grid, graph: &BlockGraph) -> Result<Self> {
+        let mut cfg = ControlFlowGraph {
+            nodes: Vec::new(),
+            entry: None,
+            index: HashMap::new(),
+        };
+        let start = State {
+            block: graph.block_at((0, 0)).id,
+            direction_pointer: Direction::Right,
+            codel_chooser: CodelChooser::Left,
+        };
+        // A program that starts on white slides to its first color block.
+        let start = if grid[0][0].name == ColorName::White {
+            slide(grid, (0, 0), Direction::Right, CodelChooser::Left).map(
+                |(position, direction_pointer, codel_chooser)| State {
+                    block: graph.block_at(position).id,
+                    direction_pointer,
+                    codel_chooser,
+                },
+            )
+        } else {
+            Some(start)
+        };
+        let mut queue = VecDeque::new();
+        if let Some(start) = start {
+            cfg.entry = Some(cfg.add(start, &mut queue));
+        }
+        while let Some(node) = queue.pop_front() {
+            let state = cfg.nodes[node].state;
+            let edge = match leave(grid, graph, state)? {
+                Some((command, to)) => {
+                    let successors = outcomes(command, to)
+                        .into_iter()
+                        .map(|state| cfg.add(state, &mut queue))
+                        .collect();
+                    Some(Edge {
+                        command,
+                        value: graph.block(state.block).size(),
+                        to,
+                        successors,
+                    })
+                }
+                None => None,
+            };
+            cfg.nodes[node].edge = edge;
+        }
+        debug!("Found {} reachable states.", cfg.nodes.len());
+        Ok(cfg)
+    }
+
+    /// The node the program starts in, or `None` if it terminates straight away.
+    pub fn entry(&self) -> Option<usize> {
+        self.entry
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// The node of `state`, if it is reachable.
+    pub fn find(&self, state: &State) -> Option<usize> {
+        self.index.get(state).copied()
+    }
+
+    // The node of `state`, adding it (and queueing it to be explored) if it is new.
+    fn add(&mut self, state: State, queue: &mut VecDeque<usize>) -> usize {
+        if let Some(&node) = self.index.get(&state) {
+            return node;
+        }
+        let node = self.nodes.len();
+        self.nodes.push(Node { state, edge: None });
+        self.index.insert(state, node);
+        queue.push_back(node);
+        node
+    }
+}
+
+// The states a move can end in once its command has run, in the order described on
+// `Edge::successors`.
+fn outcomes(command: Option<Command>, to: State) -> Vec<State> {
+    match command {
+        Some(Command::Pointer) => {
+            let mut direction_pointer = to.direction_pointer;
+            (0..4)
+                .map(|_| {
+                    let state = State {
+                        direction_pointer,
+                        ..to
+                    };
+                    direction_pointer = direction_pointer.clockwise();
+                    state
+                })
+                .collect()
+        }
+        Some(Command::Switch) => vec![
+            to,
+            State {
+                codel_chooser: to.codel_chooser.toggled(),
+                ..to
+            },
+        ],
+        _ => vec![to],
+    }
+}
+
+// Works out where the interpreter goes from `state`, the way `PietProgram::step_once`
+// does: it toggles the CC and turns the DP while the way out is blocked, and slides
+// through white. Returns the command executed on the way and the state reached, or
+// `None` if the program terminates.
+fn leave(
+    grid: &Grid,
+    graph: &BlockGraph,
+    state: State,
+) -> Result<Option<(Option<Command>, State)>> {
+    let block = graph.block(state.block);
+    let (mut direction_pointer, mut codel_chooser) = (state.direction_pointer, state.codel_chooser);
+    let mut attempts = 0;
+    let exit = loop {
+        let exit = block.exit(direction_pointer, codel_chooser);
+        if exit.next.is_some() {
+            break exit;
+        }
+        if attempts == 8 {
+            return Ok(None);
+        }
+        if attempts % 2 == 0 {
+            codel_chooser = codel_chooser.toggled();
+        } else {
+            direction_pointer = direction_pointer.clockwise();
+        }
+        attempts += 1;
+    };
+    let next = exit.next.expect("the loop only stops at an open exit");
+    let (x, y) = next;
+    let next_color = grid[y as usize][x as usize];
+    if next_color.name == ColorName::White {
+        return Ok(slide(grid, next, direction_pointer, codel_chooser).map(
+            |(position, direction_pointer, codel_chooser)| {
+                (
+                    None,
+                    State {
+                        block: graph.block_at(position).id,
+                        direction_pointer,
+                        codel_chooser,
+                    },
+                )
+            },
+        ));
+    }
+    let command = Command::get_command(
+        block.color.lightness_difference(&next_color),
+        block.color.hue_difference(&next_color),
+    )
+    .map_err(|_| {
+        PietError::Translation(format!(
+            "invalid color change from {:?} to {:?} at ({}, {})",
+            block.color.name, next_color.name, x, y
+        ))
+    })?;
+    Ok(Some((
+        Some(command),
+        State {
+            block: graph.block_at(next).id,
+            direction_pointer,
+            codel_chooser,
+        },
+    )))
+}
+
+// Slides through white from `position` like the interpreter does, and returns the codel
+// of the block it comes out in with the DP and CC at that point, or `None` if it goes
+// round in circles.
+fn slide(
+    grid: &Grid,
+    mut position: (i32, i32),
+    mut direction_pointer: Direction,
+    mut codel_chooser: CodelChooser,
+) -> Option<((i32, i32), Direction, CodelChooser)> {
+    let (width, height) = (grid[0].len() as i32, grid.len() as i32);
+    let mut visited = HashSet::new();
+    loop {
+        if !visited.insert((position, direction_pointer)) {
+            return None;
+        }
+        let (dx, dy) = direction_pointer.to_vector();
+        let (x, y) = (position.0 + dx, position.1 + dy);
+        if x < 0
+            || y < 0
+            || x >= width
+            || y >= height
+            || grid[y as usize][x as usize].name == ColorName::Black
+        {
+            codel_chooser = codel_chooser.toggled();
+            direction_pointer = direction_pointer.clockwise();
+            continue;
+        }
+        position = (x, y);
+        if grid[y as usize][x as usize].name != ColorName::White {
+            return Some((position, direction_pointer, codel_chooser));
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PietColor {
     pub name: ColorName,
@@ -143,6 +145,32 @@ impl PietColor {
             _ => Err(rgb),
         }
     }
+
+    // The inverse of from_rgb.
+    pub fn to_rgb(&self) -> [u8; 3] {
+        match self.name {
+            ColorName::Black => [0, 0, 0],
+            ColorName::White => [255, 255, 255],
+            ColorName::Red => [255, 0, 0],
+            ColorName::Yellow => [255, 255, 0],
+            ColorName::Green => [0, 255, 0],
+            ColorName::Cyan => [0, 255, 255],
+            ColorName::Blue => [0, 0, 255],
+            ColorName::Magenta => [255, 0, 255],
+            ColorName::DarkRed => [192, 0, 0],
+            ColorName::DarkYellow => [192, 192, 0],
+            ColorName::DarkGreen => [0, 192, 0],
+            ColorName::DarkCyan => [0, 192, 192],
+            ColorName::DarkBlue => [0, 0, 192],
+            ColorName::DarkMagenta => [192, 0, 192],
+            ColorName::LightRed => [255, 192, 192],
+            ColorName::LightYellow => [255, 255, 192],
+            ColorName::LightGreen => [192, 255, 192],
+            ColorName::LightCyan => [192, 255, 255],
+            ColorName::LightBlue => [192, 192, 255],
+            ColorName::LightMagenta => [255, 192, 255],
+        }
+    }
 }
 
 impl Default for PietColor {
@@ -155,7 +183,7 @@ impl Default for PietColor {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ColorName {
     Black,
     White,
@@ -178,3 +206,13 @@ pub enum ColorName {
     LightBlue,
     LightMagenta,
 }
+
+// Black or white, whichever stands out more against `rgb`, for drawing on top of a codel.
+pub(crate) fn contrast([r, g, b]: [u8; 3]) -> [u8; 3] {
+    let luma = 299 * r as u32 + 587 * g as u32 + 114 * b as u32;
+    if luma > 128_000 {
+        [0, 0, 0]
+    } else {
+        [255, 255, 255]
+    }
+}
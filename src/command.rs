@@ -1,8 +1,11 @@
+use crate::error::{PietError, Result};
 use crate::interpreter::PietProgram;
-use core::panic;
-use std::borrow::Cow;
+use crate::io::PietIo;
+use crate::value::{Operation, PietValue};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum Command {
     Black,
     White,
@@ -27,8 +30,8 @@ pub enum Command {
 }
 
 impl Command {
-    pub fn get_command(lightness_difference: i8, hue_difference: i8) -> Self {
-        match (lightness_difference, hue_difference) {
+    pub fn get_command(lightness_difference: i8, hue_difference: i8) -> Result<Self> {
+        let command = match (lightness_difference, hue_difference) {
             (0, 1) => Self::Add,
             (0, 2) => Self::Divide,
             (0, 3) => Self::Greater,
@@ -46,20 +49,56 @@ impl Command {
             (2, 3) => Self::Switch,
             (2, 4) => Self::InNumber,
             (2, 5) => Self::OutChar,
-            _ => panic!(
-                "Invalid command for : DL{} DH{}",
-                lightness_difference, hue_difference
-            ),
-        }
+            _ => {
+                return Err(PietError::runtime(format!(
+                    "Invalid command for : DL{} DH{}",
+                    lightness_difference, hue_difference
+                )))
+            }
+        };
+        Ok(command)
+    }
+
+    /// Returns true for the commands that read input or write output.
+    pub fn performs_io(&self) -> bool {
+        matches!(
+            self,
+            Self::InNumber | Self::InChar | Self::OutNumber | Self::OutChar
+        )
     }
 
-    pub fn execute(&self, context: &mut PietProgram) {
+    /// The commands that can actually be executed, i.e. everything but `Black`, `White`
+    /// and `Nothing`.
+    pub const EXECUTABLE: [Command; 17] = [
+        Self::Push,
+        Self::Pop,
+        Self::Add,
+        Self::Subtract,
+        Self::Multiply,
+        Self::Divide,
+        Self::Mod,
+        Self::Not,
+        Self::Greater,
+        Self::Pointer,
+        Self::Switch,
+        Self::Duplicate,
+        Self::Roll,
+        Self::InNumber,
+        Self::InChar,
+        Self::OutNumber,
+        Self::OutChar,
+    ];
+
+    pub fn execute<I: PietIo, V: PietValue>(&self, context: &mut PietProgram<I, V>) -> Result<()> {
         trace!("Executing command: {:?}", self);
         match self {
             Self::Push => {
-                let value = context.get_current_value();
-                context.stack.push(value);
+                let size = context.get_current_value();
+                let value = V::from_i64(size as i64).ok_or_else(|| {
+                    PietError::runtime(format!("Block size {} does not fit on the stack", size))
+                })?;
                 trace!("Pushed value: {}", value);
+                context.stack.push(value);
             }
             Self::Pop => {
                 context.stack.pop();
@@ -71,18 +110,20 @@ impl Command {
                     Some(a) => a,
                     None => {
                         debug!("{}", err);
-                        return;
+                        return Ok(());
                     }
                 };
                 let b = match context.stack.pop() {
                     Some(b) => b,
                     None => {
+                        context.stack.push(a);
                         debug!("{}", err);
-                        return;
+                        return Ok(());
                     }
                 };
-                context.stack.push(a + b);
-                trace!("Added values: {} + {} = {}", a, b, a + b);
+                let result = context.arithmetic(Operation::Add, &b, &a)?;
+                trace!("Added values: {} + {} = {}", b, a, result);
+                context.stack.push(result);
             }
             Self::Subtract => {
                 let err = "Attempted to subtract with empty stack. Ignoring.";
@@ -90,7 +131,7 @@ impl Command {
                     Some(a) => a,
                     None => {
                         debug!("{}", err);
-                        return;
+                        return Ok(());
                     }
                 };
                 let b = match context.stack.pop() {
@@ -98,11 +139,12 @@ impl Command {
                     None => {
                         context.stack.push(a);
                         debug!("{}", err);
-                        return;
+                        return Ok(());
                     }
                 };
-                context.stack.push(b - a);
-                trace!("Subtracted values: {} - {} = {}", b, a, b - a);
+                let result = context.arithmetic(Operation::Subtract, &b, &a)?;
+                trace!("Subtracted values: {} - {} = {}", b, a, result);
+                context.stack.push(result);
             }
             Self::Multiply => {
                 let err = "Attempted to multiply with empty stack. Ignoring.";
@@ -110,7 +152,7 @@ impl Command {
                     Some(a) => a,
                     None => {
                         debug!("{}", err);
-                        return;
+                        return Ok(());
                     }
                 };
                 let b = match context.stack.pop() {
@@ -118,11 +160,12 @@ impl Command {
                     None => {
                         context.stack.push(a);
                         debug!("{}", err);
-                        return;
+                        return Ok(());
                     }
                 };
-                context.stack.push(a * b);
-                trace!("Multiplied values: {} * {} = {}", a, b, a * b);
+                let result = context.arithmetic(Operation::Multiply, &b, &a)?;
+                trace!("Multiplied values: {} * {} = {}", b, a, result);
+                context.stack.push(result);
             }
             Self::Divide => {
                 let err = "Attempted to divide with empty stack. Ignoring.";
@@ -130,7 +173,7 @@ impl Command {
                     Some(a) => a,
                     None => {
                         debug!("{}", err);
-                        return;
+                        return Ok(());
                     }
                 };
                 let b = match context.stack.pop() {
@@ -138,13 +181,17 @@ impl Command {
                     None => {
                         context.stack.push(a);
                         debug!("{}", err);
-                        return;
+                        return Ok(());
                     }
                 };
-                if b != 0 {
-                    context.stack.push(b / a);
-                    trace!("Divided values: {} / {} = {}", b, a, b / a);
+                if !a.is_zero() {
+                    let result = context.arithmetic(Operation::Divide, &b, &a)?;
+                    trace!("Divided values: {} / {} = {}", b, a, result);
+                    context.stack.push(result);
                 } else {
+                    // Leave the stack as it was, as if the command had never run.
+                    context.stack.push(b);
+                    context.stack.push(a);
                     trace!("Attempted to divide by zero. Ignoring.");
                 }
             }
@@ -154,7 +201,7 @@ impl Command {
                     Some(a) => a,
                     None => {
                         debug!("{}", err);
-                        return;
+                        return Ok(());
                     }
                 };
                 let b = match context.stack.pop() {
@@ -162,13 +209,17 @@ impl Command {
                     None => {
                         context.stack.push(a);
                         debug!("{}", err);
-                        return;
+                        return Ok(());
                     }
                 };
-                if b != 0 {
-                    context.stack.push(b % a);
-                    trace!("Modulo values: {} % {} = {}", b, a, b % a);
+                if !a.is_zero() {
+                    let result = context.arithmetic(Operation::Remainder, &b, &a)?;
+                    trace!("Modulo values: {} % {} = {}", b, a, result);
+                    context.stack.push(result);
                 } else {
+                    // Leave the stack as it was, as if the command had never run.
+                    context.stack.push(b);
+                    context.stack.push(a);
                     trace!("Attempted to modulo by zero. Ignoring.");
                 }
             }
@@ -177,11 +228,13 @@ impl Command {
                     Some(a) => a,
                     None => {
                         debug!("Attempted to negate with empty stack. Ignoring.");
-                        return;
+                        return Ok(());
                     }
                 };
-                context.stack.push(if a == 0 { 1 } else { 0 });
                 trace!("Negated value: !{}", a);
+                context
+                    .stack
+                    .push(if a.is_zero() { V::one() } else { V::zero() });
             }
             Self::Greater => {
                 let err = "Attempted to compare with empty stack. Ignoring.";
@@ -189,7 +242,7 @@ impl Command {
                     Some(a) => a,
                     None => {
                         debug!("{}", err);
-                        return;
+                        return Ok(());
                     }
                 };
                 let b = match context.stack.pop() {
@@ -197,48 +250,39 @@ impl Command {
                     None => {
                         context.stack.push(a);
                         debug!("{}", err);
-                        return;
+                        return Ok(());
                     }
                 };
-                context.stack.push(if b > a { 1 } else { 0 });
-                trace!(
-                    "Compared values: {} > {} = {}",
-                    b,
-                    a,
-                    if b > a { 1 } else { 0 }
-                );
+                let result = if b > a { V::one() } else { V::zero() };
+                trace!("Compared values: {} > {} = {}", b, a, result);
+                context.stack.push(result);
             }
             Self::Pointer => {
                 let err = "Attempted to move pointer with empty stack. Ignoring.";
-                let mut a = match context.stack.pop() {
+                let a = match context.stack.pop() {
                     Some(a) => a,
                     None => {
                         debug!("{}", err);
-                        return;
+                        return Ok(());
                     }
                 };
-                let msg_a = a;
-                while a != 0 {
-                    if a > 0 {
-                        context.move_pointer_clockwise();
-                    } else {
-                        context.move_pointer_anticlockwise();
-                    }
-                    a -= 1;
+                // Four clockwise turns get back to where we started, and an anticlockwise
+                // turn is the same as three clockwise ones.
+                for _ in 0..a.rem_euclid_u64(4) {
+                    context.move_pointer_clockwise();
                 }
-                trace!("Moved pointer {} steps", msg_a);
+                trace!("Moved pointer {} steps", a);
             }
             Self::Switch => {
-                let mut a = match context.stack.pop() {
+                let a = match context.stack.pop() {
                     Some(a) => a,
                     None => {
                         debug!("Attempted to toggle codel chooser with empty stack. Ignoring.");
-                        return;
+                        return Ok(());
                     }
                 };
-                while a != 0 {
+                if a.rem_euclid_u64(2) == 1 {
                     context.toggle_codel_chooser();
-                    a -= 1;
                 }
                 trace!("Toggled codel chooser {} times", a);
             }
@@ -247,12 +291,12 @@ impl Command {
                     Some(a) => a,
                     None => {
                         debug!("Attempted to duplicate with empty stack. Ignoring.");
-                        return;
+                        return Ok(());
                     }
                 };
-                context.stack.push(a);
-                context.stack.push(a);
                 trace!("Duplicated value: {}", a);
+                context.stack.push(a.clone());
+                context.stack.push(a);
             }
             // Pops the top two values off the stack and "rolls" the remaining stack entries to a depth equal to the second value popped,
             // by a number of rolls equal to the first value popped. A single roll to depth n is defined as burying the top value on the stack n deep and bringing all values
@@ -262,20 +306,26 @@ impl Command {
                 context.stack.roll();
             }
             Self::InNumber => {
-                context.stack.in_number();
+                if let Some(number) = context.read_number()? {
+                    trace!("Input number: {}", number);
+                    context.stack.push(number);
+                }
             }
             Self::InChar => {
-                context.stack.in_char();
+                if let Some(ch) = context.read_char()? {
+                    trace!("Input character: (code point: {})", ch);
+                    context.stack.push(ch);
+                }
             }
             Self::OutNumber => {
                 let value = match context.stack.pop() {
                     Some(value) => value,
                     None => {
                         debug!("Attempted to output with empty stack. Ignoring.");
-                        return;
+                        return Ok(());
                     }
                 };
-                Self::output_number(value);
+                context.write_number(&value)?;
                 trace!("Output number: {}", value);
             }
             Self::OutChar => {
@@ -283,57 +333,110 @@ impl Command {
                     Some(value) => value,
                     None => {
                         debug!("Attempted to output with empty stack. Ignoring.");
-                        return;
+                        return Ok(());
                     }
                 };
-
-                if value < 0 || value > char::MAX as i32 {
-                    debug!(
-                        "Attempted to output invalid character: {}. Ignoring.",
-                        value
-                    );
-                    return;
-                }
-                let c = value as u8;
-
-                Self::output_char(c);
-                trace!("Output character: {}", c as char);
+                context.write_char(&value)?;
+                trace!("Output character: (code point: {})", value);
+            }
+            _ => {
+                return Err(PietError::runtime(format!(
+                    "Command not implemented: {:?}",
+                    self
+                )))
             }
-            _ => panic!("Command not implemented: {:?}", self),
         }
+        Ok(())
     }
 
-    pub fn to_forth(&self, context: &mut PietProgram) -> Cow<'static, str> {
-        match self {
-            Self::Push => {
-                let value = context.get_current_value();
-                value.to_string().into()
+    /// The name of the command in the runtimes of the translations, in snake case, e.g.
+    /// `out_char`. Each target spells it its own way.
+    pub fn runtime_name(&self) -> Result<&'static str> {
+        let name = match self {
+            Self::Push => "push",
+            Self::Pop => "pop",
+            Self::Add => "add",
+            Self::Subtract => "subtract",
+            Self::Multiply => "multiply",
+            Self::Divide => "divide",
+            Self::Mod => "mod",
+            Self::Not => "not",
+            Self::Greater => "greater",
+            Self::Pointer => "pointer",
+            Self::Switch => "switch",
+            Self::Duplicate => "duplicate",
+            Self::Roll => "roll",
+            Self::InNumber => "in_number",
+            Self::InChar => "in_char",
+            Self::OutNumber => "out_number",
+            Self::OutChar => "out_char",
+            _ => {
+                return Err(PietError::Translation(format!(
+                    "Command not implemented: {:?}",
+                    self
+                )))
             }
-            Self::Pop => "DROP".into(),
-            Self::Add => "+".into(),
-            Self::Subtract => "-".into(),
-            Self::Multiply => "*".into(),
-            Self::Divide => "/".into(),
-            Self::Mod => "%".into(),
-            Self::Not => "NOT".into(),
-            Self::Greater => "GREATER".into(),
-            Self::Pointer => "DROP".into(),
-            Self::Switch => "DROP".into(),
-            Self::Duplicate => "DUP".into(),
-            Self::Roll => "PIET-ROLL".into(),
-            Self::InNumber => "INTEGER-INPUT".into(),
-            Self::InChar => "KEY".into(),
-            Self::OutNumber => ".".into(),
-            Self::OutChar => "EMIT".into(),
-            _ => panic!("Command not implemented: {:?}", self),
-        }
+        };
+        Ok(name)
     }
 
-    fn output_char(value: u8) {
-        print!("{}", value as char);
+    /// The Forth code for the command, a word of the prelude such as `PIET-OUT-CHAR`.
+    /// `value` is the size of the block being left, which is what `Push` pushes.
+    pub fn to_forth(&self, value: usize) -> Result<String> {
+        let name = self.runtime_name()?;
+        Ok(match self {
+            Self::Push => value.to_string(),
+            _ => format!("PIET-{}", name.to_uppercase().replace('_', "-")),
+        })
     }
 
-    fn output_number(value: i32) {
-        print!("{}", value);
+    /// The C statement for the command, calling the functions of the C translation's
+    /// runtime. `value` is the size of the block being left, which is what `Push` pushes.
+    pub fn to_c(&self, value: usize) -> Result<String> {
+        let name = self.runtime_name()?;
+        Ok(match self {
+            Self::Push => format!("push({});", value),
+            _ => format!("piet_{}();", name),
+        })
+    }
+
+    /// The Rust statement for the command, calling the methods of the Rust translation's
+    /// runtime. `value` is the size of the block being left, which is what `Push` pushes.
+    pub fn to_rust(&self, value: usize) -> Result<String> {
+        // `mod` is a keyword, so the runtime calls it `modulo`.
+        let name = match self.runtime_name()? {
+            "mod" => "modulo",
+            name => name,
+        };
+        Ok(match self {
+            Self::Push => format!("m.stack.push({});", value),
+            // Only input and output can fail.
+            _ if self.performs_io() => format!("m.{}()?;", name),
+            _ => format!("m.{}();", name),
+        })
+    }
+
+    /// The WebAssembly instructions for the command, one per line, calling the functions
+    /// of the WAT translation's runtime. `value` is the size of the block being left,
+    /// which is what `Push` pushes.
+    pub fn to_wat(&self, value: usize) -> Result<String> {
+        let name = self.runtime_name()?;
+        Ok(match self {
+            Self::Push => format!("i64.const {}\ncall $push", value),
+            _ => format!("call $piet_{}", name),
+        })
+    }
+}
+
+// Parses a command name such as `OutChar`, `out_char` or `out-char`.
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let name = s.replace(['_', '-'], "").to_lowercase();
+        Self::EXECUTABLE
+            .into_iter()
+            .find(|command| format!("{:?}", command).to_lowercase() == name)
+            .ok_or_else(|| format!("unknown command {:?}", s))
     }
 }
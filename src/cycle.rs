@@ -0,0 +1,62 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use crate::{
+    graph::BlockId,
+    interpreter::{CodelChooser, Direction},
+};
+
+/// Everything that determines what a program does next, as far as the interpreter knows.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct MachineState<V> {
+    pub block: BlockId,
+    pub direction_pointer: Direction,
+    pub codel_chooser: CodelChooser,
+    pub stack: Vec<V>,
+    pub input_position: usize,
+}
+
+/// Spots a program that has provably entered an infinite loop.
+///
+/// The interpreter is deterministic, so if it is ever in exactly the same state twice
+/// without having done any I/O in between, it will go round the same cycle forever.
+#[derive(Debug, Default)]
+pub(crate) struct LoopDetector<V> {
+    // The states seen since the last I/O, in order.
+    history: Vec<MachineState<V>>,
+    // Hash of each state to its indices in `history`.
+    seen: HashMap<u64, Vec<usize>>,
+}
+
+impl<V: Clone + Eq + Hash> LoopDetector<V> {
+    pub fn new() -> Self {
+        LoopDetector {
+            history: Vec::new(),
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Forgets all states, e.g. because the program did I/O.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.seen.clear();
+    }
+
+    /// Records a state. If it was seen before, returns the states making up the cycle,
+    /// starting with the earlier occurrence.
+    pub fn record(&mut self, state: MachineState<V>) -> Option<&[MachineState<V>]> {
+        let mut hasher = DefaultHasher::new();
+        state.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let indices = self.seen.entry(hash).or_default();
+        // Compare the full states too, so that a hash collision can't fake a loop.
+        if let Some(&start) = indices.iter().find(|&&i| self.history[i] == state) {
+            return Some(&self.history[start..]);
+        }
+        indices.push(self.history.len());
+        self.history.push(state);
+        None
+    }
+}
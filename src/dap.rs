@@ -0,0 +1,532 @@
+//! A [Debug Adapter Protocol](https://microsoft.github.io/debug-adapter-protocol/) server,
+//! so that Piet programs can be debugged from an editor.
+//!
+//! Images have no lines of text, so source locations are mapped to codels: line `n` is
+//! row `n - 1` of the image and column `n` is codel `n - 1` of that row, unless the client
+//! counts lines and columns from 0. A breakpoint on a location stops the program when it
+//! enters the color block containing that codel; a breakpoint without a column is on the
+//! first codel of its row. Function breakpoints take the same syntax as `--break`, e.g.
+//! `command out_char` or `depth>10`.
+//!
+//! The program's input is read from the file named by the `input` launch argument, and
+//! its output is sent to the client as `output` events.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use serde_json::{json, Value};
+
+use crate::{
+    breakpoint::{Breakpoint, StopReason},
+    debugger::Debugger,
+    error::{PietError, Result},
+    interpreter::PietProgram,
+    io::BufferIo,
+    loader::load_image,
+    value::ArithmeticMode,
+};
+
+// Only one program runs per session, on a single thread.
+const THREAD_ID: i64 = 1;
+const STATE_VARIABLES: i64 = 1;
+const STACK_VARIABLES: i64 = 2;
+// The most steps executed between two checks for requests, so that `pause` is answered
+// promptly while the program runs.
+const STEPS_PER_CHECK: usize = 10_000;
+
+/// Serves a single debug session, reading requests from `input` and writing responses and
+/// events to `output`, until the client disconnects or closes `input`.
+pub fn serve(input: impl Read + Send + 'static, output: impl Write) -> Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    // Requests are read on their own thread, so that they can arrive while the program runs.
+    thread::spawn(move || {
+        let mut input = BufReader::new(input);
+        loop {
+            match read_message(&mut input) {
+                Ok(Some(message)) => {
+                    if sender.send(message).is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => return,
+                Err(e) => {
+                    error!("failed to read a debug adapter message: {}", e);
+                    return;
+                }
+            }
+        }
+    });
+    Session::new(output).run(receiver)
+}
+
+// Reads one message: a `Content-Length` header, a blank line and that many bytes of JSON.
+// Returns `None` at the end of input.
+fn read_message(input: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| protocol_error("message without a Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| protocol_error(format!("invalid message: {}", e)))
+}
+
+fn protocol_error(message: impl Into<String>) -> PietError {
+    PietError::Io(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        message.into(),
+    ))
+}
+
+struct Session<W> {
+    output: W,
+    seq: i64,
+    debugger: Option<Debugger<BufferIo>>,
+    // The image being debugged, as given by the client.
+    image: String,
+    // What the client counts lines and columns from.
+    line_base: i32,
+    column_base: i32,
+    // The numbers of the breakpoints set through `setBreakpoints` and
+    // `setFunctionBreakpoints`, which are replaced as a whole by every request.
+    source_breakpoints: Vec<usize>,
+    function_breakpoints: Vec<usize>,
+    stop_on_entry: bool,
+    started: bool,
+    running: bool,
+    // How much of the program's output has been sent to the client.
+    output_sent: usize,
+    done: bool,
+}
+
+impl<W: Write> Session<W> {
+    fn new(output: W) -> Self {
+        Session {
+            output,
+            seq: 0,
+            debugger: None,
+            image: String::new(),
+            line_base: 1,
+            column_base: 1,
+            source_breakpoints: Vec::new(),
+            function_breakpoints: Vec::new(),
+            stop_on_entry: false,
+            started: false,
+            running: false,
+            output_sent: 0,
+            done: false,
+        }
+    }
+
+    fn run(&mut self, requests: Receiver<Value>) -> Result<()> {
+        while !self.done {
+            let request = if self.running {
+                match requests.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            } else {
+                match requests.recv() {
+                    Ok(request) => Some(request),
+                    Err(_) => return Ok(()),
+                }
+            };
+            match request {
+                Some(request) => self.handle(&request)?,
+                None => self.run_for_a_while()?,
+            }
+        }
+        Ok(())
+    }
+
+    // Continues a running program, stopping to report why if it stops by itself.
+    fn run_for_a_while(&mut self) -> Result<()> {
+        let result = self.debugger_mut().step(STEPS_PER_CHECK);
+        if let Ok(StopReason::Stepped) = result {
+            return self.send_output();
+        }
+        self.running = false;
+        self.report_stop(result, "step")
+    }
+
+    fn handle(&mut self, request: &Value) -> Result<()> {
+        if request["type"] != "request" {
+            return Ok(());
+        }
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+        debug!("debug adapter request: {}", command);
+        let launched = self.debugger.is_some();
+        let result = match command {
+            "initialize" => Ok(self.initialize(arguments)),
+            "launch" => self.launch(arguments),
+            "disconnect" | "terminate" => {
+                self.done = true;
+                Ok(Value::Null)
+            }
+            _ if !launched => Err(format!("{} needs a launched program", command)),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setFunctionBreakpoints" => self.set_function_breakpoints(arguments),
+            "configurationDone" => Ok(Value::Null),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Interpreter", "variablesReference": STATE_VARIABLES, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK_VARIABLES, "expensive": false },
+            ] })),
+            "variables" => Ok(self.variables(arguments)),
+            "continue" => Ok(json!({ "allThreadsContinued": true })),
+            "next" | "stepIn" | "stepOut" | "stepBack" | "reverseContinue" | "pause" => {
+                Ok(Value::Null)
+            }
+            _ => Err(format!("unsupported request {:?}", command)),
+        };
+        let success = result.is_ok();
+        self.respond(request, result)?;
+        if !success {
+            return Ok(());
+        }
+        // Events that follow from a request are sent after its response.
+        match command {
+            "initialize" => {}
+            "launch" => self.send_event("initialized", Value::Null)?,
+            "configurationDone" => self.start()?,
+            "terminate" => self.send_event("terminated", Value::Null)?,
+            "continue" => self.running = true,
+            "next" | "stepIn" | "stepOut" => {
+                self.running = false;
+                let result = self.debugger_mut().step(1);
+                self.report_stop(result, "step")?;
+            }
+            "stepBack" => {
+                self.running = false;
+                let reason = self.debugger_mut().back(1);
+                self.report_stop(Ok(reason), "step")?;
+            }
+            "reverseContinue" => {
+                self.running = false;
+                let reason = self.debugger_mut().reverse_cont();
+                self.report_stop(Ok(reason), "step")?;
+            }
+            "pause" => {
+                self.running = false;
+                self.report_stop(Ok(StopReason::Stepped), "pause")?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn initialize(&mut self, arguments: &Value) -> Value {
+        if arguments["linesStartAt1"] == false {
+            self.line_base = 0;
+        }
+        if arguments["columnsStartAt1"] == false {
+            self.column_base = 0;
+        }
+        json!({
+            "supportsConfigurationDoneRequest": true,
+            "supportsFunctionBreakpoints": true,
+            "supportsStepBack": true,
+            "supportsTerminateRequest": true,
+        })
+    }
+
+    // Loads the image named by the `program` argument. Also takes `codelSize`, `input` (a
+    // file with the program's input), `arithmetic` and `stopOnEntry`.
+    fn launch(&mut self, arguments: &Value) -> std::result::Result<Value, String> {
+        if self.debugger.is_some() {
+            return Err("a program has already been launched".to_string());
+        }
+        let image = arguments["program"]
+            .as_str()
+            .ok_or("launch needs the path of an image as `program`")?;
+        let codel_size = arguments["codelSize"].as_u64().unwrap_or(1) as u32;
+        let grid = load_image(image, codel_size).map_err(|e| e.to_string())?;
+        let input = match arguments["input"].as_str() {
+            Some(path) => std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?,
+            None => Vec::new(),
+        };
+        let mode = match arguments["arithmetic"].as_str() {
+            Some(mode) => mode.parse::<ArithmeticMode>()?,
+            None => ArithmeticMode::Trapping,
+        };
+        if mode == ArithmeticMode::Promote {
+            return Err("promote arithmetic is not supported when debugging".to_string());
+        }
+        let mut program = PietProgram::with_io(grid, BufferIo::new(input));
+        program.set_arithmetic_mode(mode);
+        self.debugger = Some(Debugger::new(program));
+        self.image = image.to_string();
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        Ok(Value::Null)
+    }
+
+    // Runs the program once it has been launched and configured.
+    fn start(&mut self) -> Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        if self.stop_on_entry {
+            self.report_stop(Ok(StopReason::Stepped), "entry")
+        } else {
+            self.running = true;
+            Ok(())
+        }
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> std::result::Result<Value, String> {
+        let locations = arguments["breakpoints"]
+            .as_array()
+            .map(|breakpoints| {
+                breakpoints
+                    .iter()
+                    .map(|breakpoint| {
+                        let line = breakpoint["line"].as_i64().unwrap_or_default() as i32;
+                        let column = breakpoint["column"]
+                            .as_i64()
+                            .map_or(self.column_base, |column| column as i32);
+                        (column - self.column_base, line - self.line_base)
+                    })
+                    .collect::<Vec<(i32, i32)>>()
+            })
+            .unwrap_or_default();
+        let program = self.debugger.as_mut().expect("launched").program_mut();
+        for number in self.source_breakpoints.drain(..) {
+            program.remove_breakpoint(number);
+        }
+        let mut results = Vec::new();
+        for codel in locations {
+            let result = match program.add_breakpoint(Breakpoint::Block(codel)) {
+                Ok(number) => {
+                    self.source_breakpoints.push(number);
+                    json!({ "id": number, "verified": true })
+                }
+                Err(e) => json!({ "verified": false, "message": e.to_string() }),
+            };
+            results.push(result);
+        }
+        Ok(json!({ "breakpoints": results }))
+    }
+
+    fn set_function_breakpoints(
+        &mut self,
+        arguments: &Value,
+    ) -> std::result::Result<Value, String> {
+        let names = arguments["breakpoints"]
+            .as_array()
+            .map(|breakpoints| {
+                breakpoints
+                    .iter()
+                    .map(|breakpoint| breakpoint["name"].as_str().unwrap_or_default().to_string())
+                    .collect::<Vec<String>>()
+            })
+            .unwrap_or_default();
+        let program = self.debugger.as_mut().expect("launched").program_mut();
+        for number in self.function_breakpoints.drain(..) {
+            program.remove_breakpoint(number);
+        }
+        let mut results = Vec::new();
+        for name in names {
+            let added = name.parse::<Breakpoint>().and_then(|breakpoint| {
+                program
+                    .add_breakpoint(breakpoint)
+                    .map_err(|e| e.to_string())
+            });
+            let result = match added {
+                Ok(number) => {
+                    self.function_breakpoints.push(number);
+                    json!({ "id": number, "verified": true })
+                }
+                Err(message) => json!({ "verified": false, "message": message }),
+            };
+            results.push(result);
+        }
+        Ok(json!({ "breakpoints": results }))
+    }
+
+    // The program has no calls, so there is a single frame: the current codel.
+    fn stack_trace(&self) -> Value {
+        let program = self.debugger().program();
+        let block = program.current_block();
+        let (x, y) = program.position();
+        let name = std::path::Path::new(&self.image)
+            .file_name()
+            .map_or(self.image.clone(), |name| {
+                name.to_string_lossy().into_owned()
+            });
+        json!({
+            "stackFrames": [{
+                "id": 0,
+                "name": format!("block {} ({:?}, {} codels)", block.id, block.color.name, block.size),
+                "source": { "name": name, "path": self.image },
+                "line": y + self.line_base,
+                "column": x + self.column_base,
+            }],
+            "totalFrames": 1,
+        })
+    }
+
+    fn variables(&self, arguments: &Value) -> Value {
+        let program = self.debugger().program();
+        let stack = program.stack();
+        let variables = match arguments["variablesReference"].as_i64() {
+            Some(STATE_VARIABLES) => {
+                let block = program.current_block();
+                let (x, y) = program.position();
+                let exit = program
+                    .graph()
+                    .block(block.id)
+                    .exit(program.direction_pointer(), program.codel_chooser());
+                let next = match exit.next {
+                    Some((next_x, next_y)) => format!("({}, {})", next_x, next_y),
+                    None => "blocked".to_string(),
+                };
+                vec![
+                    variable("step", program.steps(), 0),
+                    variable("position", format!("({}, {})", x, y), 0),
+                    variable(
+                        "block",
+                        format!(
+                            "{} ({:?}, {} codels)",
+                            block.id, block.color.name, block.size
+                        ),
+                        0,
+                    ),
+                    variable(
+                        "exit",
+                        format!("({}, {}) -> {}", exit.codel.0, exit.codel.1, next),
+                        0,
+                    ),
+                    variable("DP", format!("{:?}", program.direction_pointer()), 0),
+                    variable("CC", format!("{:?}", program.codel_chooser()), 0),
+                    variable("stack", format!("[{}]", stack), STACK_VARIABLES),
+                    variable("output", format!("{} bytes", program.output_position()), 0),
+                ]
+            }
+            // Top of the stack first, as everywhere else.
+            Some(STACK_VARIABLES) => stack
+                .values()
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(index, value)| variable(&index.to_string(), value, 0))
+                .collect(),
+            _ => Vec::new(),
+        };
+        json!({ "variables": variables })
+    }
+
+    // Tells the client why the program stopped, after sending the output written so far.
+    // `reason` is used when the program stopped for no particular reason.
+    fn report_stop(&mut self, result: Result<StopReason>, reason: &str) -> Result<()> {
+        self.send_output()?;
+        let body = match result {
+            Ok(StopReason::Breakpoint(number)) => {
+                json!({ "reason": "breakpoint", "threadId": THREAD_ID, "hitBreakpointIds": [number] })
+            }
+            Ok(StopReason::Terminated) => {
+                self.send_event("exited", json!({ "exitCode": 0 }))?;
+                return self.send_event("terminated", Value::Null);
+            }
+            Ok(StopReason::HistoryStart) => json!({
+                "reason": reason,
+                "threadId": THREAD_ID,
+                "description": "Reached the start of the recorded history",
+            }),
+            Ok(StopReason::Stepped) => json!({ "reason": reason, "threadId": THREAD_ID }),
+            Err(e) => {
+                let message = e.to_string();
+                self.send_event(
+                    "output",
+                    json!({ "category": "stderr", "output": format!("{}\n", message) }),
+                )?;
+                json!({ "reason": "exception", "threadId": THREAD_ID, "text": message })
+            }
+        };
+        self.send_event("stopped", body)
+    }
+
+    // Sends the program output that the client has not seen yet.
+    fn send_output(&mut self) -> Result<()> {
+        let output = self.debugger().program().io().output();
+        if output.len() <= self.output_sent {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&output[self.output_sent..]).into_owned();
+        self.output_sent = output.len();
+        self.send_event("output", json!({ "category": "stdout", "output": text }))
+    }
+
+    fn debugger(&self) -> &Debugger<BufferIo> {
+        self.debugger.as_ref().expect("launched")
+    }
+
+    fn debugger_mut(&mut self) -> &mut Debugger<BufferIo> {
+        self.debugger.as_mut().expect("launched")
+    }
+
+    fn respond(
+        &mut self,
+        request: &Value,
+        result: std::result::Result<Value, String>,
+    ) -> Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => {}
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = Value::String(message),
+        }
+        self.send(response)
+    }
+
+    fn send_event(&mut self, event: &str, body: Value) -> Result<()> {
+        let mut message = json!({ "type": "event", "event": event });
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.send(message)
+    }
+
+    fn send(&mut self, mut message: Value) -> Result<()> {
+        self.seq += 1;
+        message["seq"] = Value::from(self.seq);
+        let body = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+fn variable(name: &str, value: impl ToString, reference: i64) -> Value {
+    json!({ "name": name, "value": value.to_string(), "variablesReference": reference })
+}
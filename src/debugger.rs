@@ -0,0 +1,357 @@
+use std::io::{BufRead, Write};
+
+use crate::{
+    breakpoint::{Breakpoint, StopReason},
+    error::Result,
+    interpreter::{PietProgram, DEFAULT_HISTORY_LIMIT},
+    io::PietIo,
+    value::PietValue,
+};
+
+/// Runs a [`PietProgram`] under control of the user, stopping at its breakpoints.
+///
+/// The debugger turns on the program's history, so that it can also be run backwards over
+/// its last [`DEFAULT_HISTORY_LIMIT`] steps.
+#[derive(Debug)]
+pub struct Debugger<I: PietIo, V: PietValue = i64> {
+    program: PietProgram<I, V>,
+}
+
+impl<I: PietIo, V: PietValue> Debugger<I, V> {
+    pub fn new(mut program: PietProgram<I, V>) -> Self {
+        program.set_history_limit(DEFAULT_HISTORY_LIMIT);
+        Debugger { program }
+    }
+
+    pub fn program(&self) -> &PietProgram<I, V> {
+        &self.program
+    }
+
+    pub fn program_mut(&mut self) -> &mut PietProgram<I, V> {
+        &mut self.program
+    }
+
+    /// Consumes the debugger, returning the program it was running.
+    pub fn into_program(self) -> PietProgram<I, V> {
+        self.program
+    }
+
+    /// Executes up to `count` steps, stopping early at a breakpoint or when the program
+    /// terminates.
+    pub fn step(&mut self, count: usize) -> Result<StopReason> {
+        for _ in 0..count {
+            if let Some(reason) = self.step_and_check()? {
+                return Ok(reason);
+            }
+        }
+        Ok(StopReason::Stepped)
+    }
+
+    /// Runs until a breakpoint is hit or the program terminates.
+    pub fn cont(&mut self) -> Result<StopReason> {
+        loop {
+            if let Some(reason) = self.step_and_check()? {
+                return Ok(reason);
+            }
+        }
+    }
+
+    /// Undoes up to `count` steps.
+    pub fn back(&mut self, count: usize) -> StopReason {
+        for _ in 0..count {
+            if !self.program.step_back() {
+                return StopReason::HistoryStart;
+            }
+        }
+        StopReason::Stepped
+    }
+
+    /// Runs backwards to the most recent state in which one of the breakpoints that are set
+    /// now would have been hit.
+    pub fn reverse_cont(&mut self) -> StopReason {
+        loop {
+            if !self.program.step_back() {
+                return StopReason::HistoryStart;
+            }
+            if let Some(number) = self.program.last_breakpoint() {
+                return StopReason::Breakpoint(number);
+            }
+        }
+    }
+
+    /// Runs backwards to just before output byte `byte` (counting from 0) was written.
+    pub fn back_to_output(&mut self, byte: usize) -> StopReason {
+        while self.program.output_position() > byte {
+            if !self.program.step_back() {
+                return StopReason::HistoryStart;
+            }
+        }
+        StopReason::Stepped
+    }
+
+    fn step_and_check(&mut self) -> Result<Option<StopReason>> {
+        if self.program.is_terminated() {
+            return Ok(Some(StopReason::Terminated));
+        }
+        let outcome = self.program.step_once()?;
+        if outcome.terminated {
+            return Ok(Some(StopReason::Terminated));
+        }
+        Ok(outcome.breakpoint.map(StopReason::Breakpoint))
+    }
+
+    /// Runs an interactive session, reading commands from `input` and writing responses to
+    /// `output`, until `quit` or the end of `input`. Type `help` for a list of commands.
+    pub fn repl(&mut self, mut input: impl BufRead, mut output: impl Write) -> Result<()> {
+        writeln!(output, "Type `help` for a list of commands.")?;
+        write_location(&self.program, &mut output)?;
+        let mut line = String::new();
+        loop {
+            write!(output, "(piet) ")?;
+            output.flush()?;
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(output)?;
+                return Ok(());
+            }
+            let command = match ReplCommand::parse(&line) {
+                Ok(Some(command)) => command,
+                Ok(None) => continue,
+                Err(message) => {
+                    writeln!(output, "{}", message)?;
+                    continue;
+                }
+            };
+            if command == ReplCommand::Quit {
+                return Ok(());
+            }
+            self.run_command(command, &mut output)?;
+        }
+    }
+
+    fn run_command(&mut self, command: ReplCommand, output: &mut impl Write) -> Result<()> {
+        match command {
+            ReplCommand::Step(count) => {
+                let result = self.step(count);
+                self.report_stop(result, output)?;
+            }
+            ReplCommand::Continue => {
+                let result = self.cont();
+                self.report_stop(result, output)?;
+            }
+            ReplCommand::Back(count) => {
+                let reason = self.back(count);
+                self.report_stop(Ok(reason), output)?;
+            }
+            ReplCommand::ReverseContinue => {
+                let reason = self.reverse_cont();
+                self.report_stop(Ok(reason), output)?;
+            }
+            ReplCommand::BackToOutput(byte) => {
+                let reason = self.back_to_output(byte);
+                self.report_stop(Ok(reason), output)?;
+            }
+            ReplCommand::Break(breakpoint) => {
+                // A block breakpoint without a codel is on the current block.
+                let breakpoint = breakpoint.unwrap_or(Breakpoint::Block(self.program.position()));
+                match self.program.add_breakpoint(breakpoint) {
+                    Ok(number) => writeln!(output, "Breakpoint {}: {}", number, breakpoint)?,
+                    Err(e) => writeln!(output, "{}", e)?,
+                }
+            }
+            ReplCommand::ListBreakpoints => {
+                let breakpoints = self.program.breakpoints();
+                if breakpoints.is_empty() {
+                    writeln!(output, "No breakpoints.")?;
+                }
+                for (number, breakpoint) in breakpoints {
+                    writeln!(output, "{}: {}", number, breakpoint)?;
+                }
+            }
+            ReplCommand::Delete(number) => match self.program.remove_breakpoint(number) {
+                Some(_) => writeln!(output, "Deleted breakpoint {}.", number)?,
+                None => writeln!(output, "No breakpoint {}.", number)?,
+            },
+            ReplCommand::Print(PrintTarget::Stack) => write_stack(&self.program, output)?,
+            ReplCommand::Print(PrintTarget::DirectionPointer) => {
+                writeln!(output, "DP: {:?}", self.program.direction_pointer())?;
+            }
+            ReplCommand::Print(PrintTarget::CodelChooser) => {
+                writeln!(output, "CC: {:?}", self.program.codel_chooser())?;
+            }
+            ReplCommand::Print(PrintTarget::Output) => {
+                writeln!(
+                    output,
+                    "Output: {} bytes written",
+                    self.program.output_position()
+                )?;
+            }
+            ReplCommand::Where => write_location(&self.program, output)?,
+            ReplCommand::Help => write!(output, "{}", HELP)?,
+            ReplCommand::Quit => {}
+        }
+        Ok(())
+    }
+
+    // Tells the user where and why execution stopped, or what went wrong.
+    fn report_stop(&mut self, result: Result<StopReason>, output: &mut impl Write) -> Result<()> {
+        // Program output goes to the same terminal, so get it out before our own.
+        self.program.io_mut().flush()?;
+        match result {
+            Ok(StopReason::Stepped) => {}
+            Ok(StopReason::Breakpoint(number)) => writeln!(output, "Breakpoint {} hit.", number)?,
+            Ok(StopReason::Terminated) => writeln!(output, "The program has terminated.")?,
+            Ok(StopReason::HistoryStart) => {
+                writeln!(output, "Reached the start of the recorded history.")?
+            }
+            Err(e) => writeln!(output, "{}", e)?,
+        }
+        write_location(&self.program, output)
+    }
+}
+
+/// Writes where the program is and what is on its stack, e.g. when it hits a breakpoint.
+pub fn write_state<I: PietIo, V: PietValue>(
+    program: &PietProgram<I, V>,
+    output: &mut impl Write,
+) -> Result<()> {
+    write_location(program, output)?;
+    write_stack(program, output)
+}
+
+// Writes the step count, the current block and where the interpreter leaves it next.
+fn write_location<I: PietIo, V: PietValue>(
+    program: &PietProgram<I, V>,
+    output: &mut impl Write,
+) -> Result<()> {
+    let block = program.current_block();
+    let (x, y) = program.position();
+    let dp = program.direction_pointer();
+    let cc = program.codel_chooser();
+    let exit = program.graph().block(block.id).exit(dp, cc);
+    writeln!(
+        output,
+        "Step {}: at ({}, {}) in block {} ({:?}, {} codels), DP {:?}, CC {:?}",
+        program.steps(),
+        x,
+        y,
+        block.id,
+        block.color.name,
+        block.size,
+        dp,
+        cc
+    )?;
+    match exit.next {
+        Some(next) => writeln!(
+            output,
+            "Exit codel ({}, {}) leads to ({}, {})",
+            exit.codel.0, exit.codel.1, next.0, next.1
+        )?,
+        None => writeln!(
+            output,
+            "Exit codel ({}, {}) is blocked",
+            exit.codel.0, exit.codel.1
+        )?,
+    }
+    Ok(())
+}
+
+fn write_stack<I: PietIo, V: PietValue>(
+    program: &PietProgram<I, V>,
+    output: &mut impl Write,
+) -> Result<()> {
+    let stack = program.stack();
+    writeln!(output, "Stack (len {}, top first): {}", stack.len(), stack)?;
+    Ok(())
+}
+
+const HELP: &str = "\
+step [n]          execute n steps (default 1)
+continue          run until a breakpoint is hit or the program terminates
+back [n]          undo n steps (default 1)
+reverse-continue  run backwards to the previous breakpoint hit
+back output n     run backwards to before output byte n (counting from 0) was written
+break x,y         stop when the interpreter arrives on codel (x, y)
+break block [x,y] stop when the interpreter enters the block containing (x, y)
+                  (default: the current block)
+break command c   stop after command c (e.g. out_char, roll) is executed
+break depth>n     stop when the stack grows beyond n values (or depth<n: shrinks below)
+break top=v       stop when a command leaves v on top of the stack
+break pointer     stop when a pointer or switch command changes the DP or CC
+break             list breakpoints
+delete n          delete breakpoint n
+print stack       show the stack, top first
+print dp          show the direction pointer
+print cc          show the codel chooser
+print output      show how many output bytes have been written
+where             show the current block, its size and its exit codel
+help              show this help
+quit              leave the debugger
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PrintTarget {
+    Stack,
+    DirectionPointer,
+    CodelChooser,
+    Output,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReplCommand {
+    Step(usize),
+    Continue,
+    Back(usize),
+    ReverseContinue,
+    BackToOutput(usize),
+    // `None` for a block breakpoint on the current block.
+    Break(Option<Breakpoint>),
+    ListBreakpoints,
+    Delete(usize),
+    Print(PrintTarget),
+    Where,
+    Help,
+    Quit,
+}
+
+impl ReplCommand {
+    // Parses a line of user input. Returns `None` for a blank line.
+    fn parse(line: &str) -> std::result::Result<Option<Self>, String> {
+        let words = line.split_whitespace().collect::<Vec<&str>>();
+        let command = match words.as_slice() {
+            [] => return Ok(None),
+            ["step" | "s"] => ReplCommand::Step(1),
+            ["step" | "s", count] => ReplCommand::Step(parse_number(count)?),
+            ["continue" | "c"] => ReplCommand::Continue,
+            ["back"] => ReplCommand::Back(1),
+            ["back", "output", byte] => ReplCommand::BackToOutput(parse_number(byte)?),
+            ["back", count] => ReplCommand::Back(parse_number(count)?),
+            ["reverse-continue" | "rc"] => ReplCommand::ReverseContinue,
+            ["break" | "b"] => ReplCommand::ListBreakpoints,
+            ["break" | "b", "block"] => ReplCommand::Break(None),
+            ["break" | "b", breakpoint @ ..] => {
+                ReplCommand::Break(Some(breakpoint.join(" ").parse()?))
+            }
+            ["delete" | "d", number] => ReplCommand::Delete(parse_number(number)?),
+            ["print" | "p", "stack"] => ReplCommand::Print(PrintTarget::Stack),
+            ["print" | "p", "dp"] => ReplCommand::Print(PrintTarget::DirectionPointer),
+            ["print" | "p", "cc"] => ReplCommand::Print(PrintTarget::CodelChooser),
+            ["print" | "p", "output"] => ReplCommand::Print(PrintTarget::Output),
+            ["where" | "w"] => ReplCommand::Where,
+            ["help" | "h" | "?"] => ReplCommand::Help,
+            ["quit" | "q" | "exit"] => ReplCommand::Quit,
+            _ => {
+                return Err(format!(
+                    "Unknown command {:?}. Type `help` for a list of commands.",
+                    line.trim()
+                ))
+            }
+        };
+        Ok(Some(command))
+    }
+}
+
+fn parse_number(text: &str) -> std::result::Result<usize, String> {
+    text.parse()
+        .map_err(|_| format!("{:?} is not a valid count", text))
+}
@@ -0,0 +1,132 @@
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong while loading, running or translating a Piet program.
+#[derive(Debug)]
+pub enum PietError {
+    /// The image could not be opened or decoded, or does not fit the codel size.
+    Image(image::ImageError),
+    /// A codel has a color that is not one of the 20 Piet colors.
+    Color { x: u32, y: u32, rgb: [u8; 3] },
+    /// Reading program input or writing program output failed.
+    Io(io::Error),
+    /// The Forth translation could not be produced or written.
+    Translation(String),
+    /// The interpreter reached a state it cannot continue from.
+    Runtime {
+        position: Option<(i32, i32)>,
+        message: String,
+    },
+    /// The program returned to a state it was in before without doing any I/O in between,
+    /// so it would loop forever. `blocks` holds the top-left codel of each block on the cycle.
+    InfiniteLoop {
+        step: u64,
+        cycle_length: usize,
+        blocks: Vec<(i32, i32)>,
+    },
+    /// A state snapshot could not be read, or does not fit the program it was resumed with.
+    Snapshot(String),
+    /// A trace file could not be read.
+    Trace(String),
+}
+
+pub type Result<T> = std::result::Result<T, PietError>;
+
+impl PietError {
+    pub fn runtime(message: impl Into<String>) -> Self {
+        PietError::Runtime {
+            position: None,
+            message: message.into(),
+        }
+    }
+
+    /// Attaches a codel position to a runtime error that doesn't have one yet.
+    pub fn at(self, position: (i32, i32)) -> Self {
+        match self {
+            PietError::Runtime {
+                position: None,
+                message,
+            } => PietError::Runtime {
+                position: Some(position),
+                message,
+            },
+            other => other,
+        }
+    }
+
+    /// The process exit code the command line tool uses for this kind of error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            PietError::Image(_) => 2,
+            PietError::Color { .. } => 3,
+            PietError::Io(_) => 4,
+            PietError::Translation(_) => 5,
+            PietError::Runtime { .. } => 6,
+            PietError::InfiniteLoop { .. } => 7,
+            PietError::Snapshot(_) => 8,
+            PietError::Trace(_) => 9,
+        }
+    }
+}
+
+impl fmt::Display for PietError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PietError::Image(e) => write!(f, "image error: {}", e),
+            PietError::Color { x, y, rgb } => {
+                write!(f, "invalid color {:?} at codel ({}, {})", rgb, x, y)
+            }
+            PietError::Io(e) => write!(f, "I/O error: {}", e),
+            PietError::Translation(message) => write!(f, "translation error: {}", message),
+            PietError::Runtime {
+                position: Some((x, y)),
+                message,
+            } => write!(f, "runtime error at codel ({}, {}): {}", x, y, message),
+            PietError::Runtime {
+                position: None,
+                message,
+            } => write!(f, "runtime error: {}", message),
+            PietError::InfiniteLoop {
+                step,
+                cycle_length,
+                blocks,
+            } => {
+                let blocks = blocks
+                    .iter()
+                    .map(|(x, y)| format!("({}, {})", x, y))
+                    .collect::<Vec<String>>();
+                write!(
+                    f,
+                    "infinite loop detected at step {}: a cycle of {} steps through the blocks at {}",
+                    step,
+                    cycle_length,
+                    blocks.join(", ")
+                )
+            }
+            PietError::Snapshot(message) => write!(f, "snapshot error: {}", message),
+            PietError::Trace(message) => write!(f, "trace error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for PietError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PietError::Image(e) => Some(e),
+            PietError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PietError {
+    fn from(e: io::Error) -> Self {
+        PietError::Io(e)
+    }
+}
+
+impl From<image::ImageError> for PietError {
+    fn from(e: image::ImageError) -> Self {
+        PietError::Image(e)
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{
+    color::{ColorName, PietColor},
+    interpreter::{CodelChooser, Direction},
+    loader::Grid,
+};
+
+/// Index of a color block in a [`BlockGraph`].
+pub type BlockId = usize;
+
+/// Where the interpreter goes when it leaves a block with a given DP and CC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exit {
+    /// The codel of the block the interpreter leaves from.
+    pub codel: (i32, i32),
+    /// The codel next to `codel` in the direction of the DP, or `None` if that is
+    /// off the edge of the program or black.
+    pub next: Option<(i32, i32)>,
+    /// The block `next` belongs to.
+    pub target: Option<BlockId>,
+}
+
+/// A contiguous area of codels of the same color.
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub id: BlockId,
+    pub color: PietColor,
+    /// The block's codels, starting with its topmost, leftmost codel.
+    pub codels: Vec<(i32, i32)>,
+    // Indexed by `exit_index`.
+    exits: [Exit; 8],
+}
+
+impl Block {
+    pub fn size(&self) -> usize {
+        self.codels.len()
+    }
+
+    pub fn exit(&self, direction_pointer: Direction, codel_chooser: CodelChooser) -> &Exit {
+        &self.exits[exit_index(direction_pointer, codel_chooser)]
+    }
+}
+
+/// Every color block of a program, labelled once up front together with where each of
+/// them is left for every DP/CC combination, so that running the program is just a
+/// matter of table lookups.
+#[derive(Debug, Clone)]
+pub struct BlockGraph {
+    blocks: Vec<Block>,
+    // The block each codel belongs to, indexed as `labels[y][x]`.
+    labels: Vec<Vec<BlockId>>,
+}
+
+impl BlockGraph {
+    pub fn compile(grid: &Grid) -> Self {
+        let start_time = std::time::Instant::now();
+        let height = grid.len();
+        let width = grid.first().map_or(0, |row| row.len());
+        let mut labels = vec![vec![usize::MAX; width]; height];
+        let mut block_codels = Vec::new();
+
+        // Label the blocks with a flood fill from each codel that isn't labelled yet.
+        // Scanning row by row means the first codel of each block is its topmost, leftmost one.
+        for y in 0..height {
+            for x in 0..width {
+                if labels[y][x] != usize::MAX {
+                    continue;
+                }
+                let id = block_codels.len();
+                let color = grid[y][x];
+                let mut codels = Vec::new();
+                let mut stack = vec![(x as i32, y as i32)];
+                labels[y][x] = id;
+                while let Some(current) = stack.pop() {
+                    codels.push(current);
+                    for direction in Direction::ALL {
+                        let (dx, dy) = direction.to_vector();
+                        let (nx, ny) = (current.0 + dx, current.1 + dy);
+                        if nx >= 0
+                            && ny >= 0
+                            && (nx as usize) < width
+                            && (ny as usize) < height
+                            && labels[ny as usize][nx as usize] == usize::MAX
+                            && grid[ny as usize][nx as usize] == color
+                        {
+                            labels[ny as usize][nx as usize] = id;
+                            stack.push((nx, ny));
+                        }
+                    }
+                }
+                codels.sort_by_key(|&(x, y)| (y, x));
+                block_codels.push((color, codels));
+            }
+        }
+
+        let blocks = block_codels
+            .into_iter()
+            .enumerate()
+            .map(|(id, (color, codels))| {
+                let exits = [
+                    (Direction::Right, CodelChooser::Left),
+                    (Direction::Right, CodelChooser::Right),
+                    (Direction::Down, CodelChooser::Left),
+                    (Direction::Down, CodelChooser::Right),
+                    (Direction::Left, CodelChooser::Left),
+                    (Direction::Left, CodelChooser::Right),
+                    (Direction::Up, CodelChooser::Left),
+                    (Direction::Up, CodelChooser::Right),
+                ]
+                .map(|(dp, cc)| find_exit(grid, &labels, &codels, dp, cc));
+                Block {
+                    id,
+                    color,
+                    codels,
+                    exits,
+                }
+            })
+            .collect::<Vec<Block>>();
+        debug!(
+            "Compiled {} color blocks in {:?}",
+            blocks.len(),
+            start_time.elapsed()
+        );
+        BlockGraph { blocks, labels }
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id]
+    }
+
+    /// The block the codel at `position` belongs to.
+    pub fn block_at(&self, position: (i32, i32)) -> &Block {
+        &self.blocks[self.labels[position.1 as usize][position.0 as usize]]
+    }
+}
+
+fn exit_index(direction_pointer: Direction, codel_chooser: CodelChooser) -> usize {
+    let dp = match direction_pointer {
+        Direction::Right => 0,
+        Direction::Down => 1,
+        Direction::Left => 2,
+        Direction::Up => 3,
+    };
+    let cc = match codel_chooser {
+        CodelChooser::Left => 0,
+        CodelChooser::Right => 1,
+    };
+    dp * 2 + cc
+}
+
+// Finds the codel on the block's furthest edge in the direction of the DP that is furthest
+// in the direction the CC picks, and what lies beyond it.
+fn find_exit(
+    grid: &Grid,
+    labels: &[Vec<BlockId>],
+    codels: &[(i32, i32)],
+    direction_pointer: Direction,
+    codel_chooser: CodelChooser,
+) -> Exit {
+    let edge_key = |c: &(i32, i32)| match direction_pointer {
+        Direction::Right => c.0,
+        Direction::Down => c.1,
+        Direction::Left => -c.0,
+        Direction::Up => -c.1,
+    };
+    let chooser_key = |c: &(i32, i32)| match direction_pointer.choose_codel(codel_chooser) {
+        Direction::Right => c.0,
+        Direction::Down => c.1,
+        Direction::Left => -c.0,
+        Direction::Up => -c.1,
+    };
+    let codel = *codels
+        .iter()
+        .max_by_key(|c| (edge_key(c), chooser_key(c)))
+        .expect("a block has at least one codel");
+
+    let (dx, dy) = direction_pointer.to_vector();
+    let (nx, ny) = (codel.0 + dx, codel.1 + dy);
+    let inside = nx >= 0 && ny >= 0 && (ny as usize) < grid.len() && (nx as usize) < grid[0].len();
+    if !inside || grid[ny as usize][nx as usize].name == ColorName::Black {
+        return Exit {
+            codel,
+            next: None,
+            target: None,
+        };
+    }
+    Exit {
+        codel,
+        next: Some((nx, ny)),
+        target: Some(labels[ny as usize][nx as usize]),
+    }
+}
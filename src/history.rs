@@ -0,0 +1,74 @@
+use std::collections::VecDeque;
+
+use crate::command::Command;
+use crate::interpreter::{CodelChooser, Direction};
+
+/// What it takes to undo a single step.
+#[derive(Debug, Clone)]
+pub(crate) struct UndoEntry<V> {
+    pub position: (i32, i32),
+    pub direction_pointer: Direction,
+    pub codel_chooser: CodelChooser,
+    pub steps: u64,
+    pub terminated: bool,
+    // The stack is undone by cutting it back to `stack_kept` values and pushing
+    // `stack_removed` (bottom first) on top: the values the step's command could touch.
+    pub stack_kept: usize,
+    pub stack_removed: Vec<V>,
+    // The read-ahead input from before the step, next byte last.
+    pub pending_input: Vec<u8>,
+    // The bytes the step read from the program's input, in order.
+    pub input: Vec<u8>,
+    pub output_position: usize,
+    // The command the step executed, if any, with the DP and CC from just before it ran:
+    // what breakpoints are checked against when running backwards.
+    pub command: Option<(Command, Direction, CodelChooser)>,
+}
+
+/// The undo log of a program, one entry per step, oldest first. With a limit, only the
+/// most recent steps are kept.
+#[derive(Debug, Default)]
+pub(crate) struct History<V> {
+    entries: VecDeque<UndoEntry<V>>,
+    limit: Option<usize>,
+}
+
+impl<V> History<V> {
+    pub fn new(limit: Option<usize>) -> Self {
+        History {
+            entries: VecDeque::new(),
+            limit,
+        }
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Adds the entry of the step just taken, forgetting the oldest one if the log is full.
+    pub fn push(&mut self, entry: UndoEntry<V>) {
+        if self.limit == Some(0) {
+            return;
+        }
+        if Some(self.entries.len()) == self.limit {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn pop(&mut self) -> Option<UndoEntry<V>> {
+        self.entries.pop_back()
+    }
+
+    pub fn last(&self) -> Option<&UndoEntry<V>> {
+        self.entries.back()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
@@ -1,66 +1,319 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
 use crate::{
+    breakpoint::{Breakpoint, StopReason},
     color::{ColorName, PietColor},
     command::Command,
+    cycle::{LoopDetector, MachineState},
+    error::{PietError, Result},
+    graph::{BlockGraph, BlockId},
+    history::{History, UndoEntry},
+    io::{PietIo, StdIo},
+    loader::Grid,
+    profile::Profile,
+    snapshot::{self, Snapshot, SNAPSHOT_VERSION},
     stack::Stack,
-    translator::Translator,
+    trace::{TraceBlock, TraceHeader, TraceStep, TraceWriter, TOP_VALUES, TRACE_VERSION},
+    value::{ArithmeticMode, Operation, PietValue},
 };
 
+/// How many steps the debuggers keep in the undo log, so that long runs don't use up all
+/// memory (see [`PietProgram::set_history_limit`]).
+pub const DEFAULT_HISTORY_LIMIT: usize = 100_000;
+
 #[derive(Debug)]
-pub struct PietProgram {
+pub struct PietProgram<I: PietIo = StdIo, V: PietValue = i64> {
     // The Piet program is a 2D grid of codels, each of which is a color.
-    grid: Vec<Vec<PietColor>>,
+    grid: Grid,
+    // The color blocks of the grid, and how they connect to each other.
+    graph: BlockGraph,
     // The stack is a LIFO data structure that holds integers. Piet is a stack-based language.
-    pub stack: Stack,
+    pub stack: Stack<V>,
     // The DP is the direction pointer. It points in one of four directions: right, down, left, or up.
     direction_pointer: Direction,
     // The CC is the codel chooser. It points in one of two directions: right or left.
-    codel_chooser: Direction,
+    codel_chooser: CodelChooser,
     // The position is the current position of the interpreter in the grid.
     position: (i32, i32),
     // The current value is the current value of the color block that our interpreter is on.
-    current_value: i32,
+    current_value: usize,
+    // The number of steps executed so far.
+    steps: u64,
+    // Whether the program has terminated by running out of ways to leave a block.
+    terminated: bool,
+    // Where the program reads its input from and writes its output to.
+    io: I,
+    // Input bytes that were read ahead (e.g. while parsing a number) but not consumed yet.
+    pending_input: Vec<u8>,
+    // The number of input bytes read from `io` so far.
+    input_read: usize,
+    // The number of output bytes written by commands so far.
+    output_written: usize,
+    // Set when infinite loop detection is turned on.
+    loop_detector: Option<LoopDetector<V>>,
+    // What to do when an arithmetic command overflows.
+    arithmetic_mode: ArithmeticMode,
+    // Whether an arithmetic command overflowed during the current step.
+    overflowed: bool,
+    // Breakpoints with the number they were given when set.
+    breakpoints: Vec<(usize, Breakpoint)>,
+    next_breakpoint: usize,
+    // Set when stepping back is turned on.
+    history: Option<History<V>>,
+    // The undo entry of the step being executed, if history is on.
+    recording: Option<UndoEntry<V>>,
+    // Set when a trace of every step is being written.
+    trace: Option<TraceWriter>,
+    // Set when block entries and moves are being counted.
+    profile: Option<Profile>,
 }
 
 impl PietProgram {
-    pub fn new(grid: Vec<Vec<PietColor>>) -> Self {
-        // convert each character in the input string to its ASCII value
-        // and put it on the stack
+    /// Creates a program that talks to the process's stdin and stdout.
+    pub fn new(grid: Grid) -> Self {
+        Self::with_io(grid, StdIo::new())
+    }
+}
+
+impl<I: PietIo, V: PietValue> PietProgram<I, V> {
+    /// Creates a program that reads its input from and writes its output to `io`.
+    pub fn with_io(grid: Grid, io: I) -> Self {
         let piet_stack = Stack::new();
+        let graph = BlockGraph::compile(&grid);
         PietProgram {
             grid,
+            graph,
             stack: piet_stack,
             direction_pointer: Direction::Right,
-            codel_chooser: Direction::Left,
+            codel_chooser: CodelChooser::Left,
             position: (0, 0),
             current_value: 0,
+            steps: 0,
+            terminated: false,
+            io,
+            pending_input: Vec::new(),
+            input_read: 0,
+            output_written: 0,
+            loop_detector: None,
+            arithmetic_mode: ArithmeticMode::default(),
+            overflowed: false,
+            breakpoints: Vec::new(),
+            next_breakpoint: 1,
+            history: None,
+            recording: None,
+            trace: None,
+            profile: None,
         }
     }
 
     // getters and setters
 
+    /// The program's stack, as left by the commands executed so far.
+    pub fn stack(&self) -> &Stack<V> {
+        &self.stack
+    }
+
+    /// The number of steps executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Returns true once the program has halted on its own, as opposed to
+    /// being stopped by the step limit.
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    pub fn arithmetic_mode(&self) -> ArithmeticMode {
+        self.arithmetic_mode
+    }
+
+    pub fn set_arithmetic_mode(&mut self, mode: ArithmeticMode) {
+        self.arithmetic_mode = mode;
+    }
+
+    /// Turns detection of provably infinite loops on or off. When it is on, a step that
+    /// returns the program to a state it was already in since its last I/O fails with
+    /// [`PietError::InfiniteLoop`]. This costs memory proportional to the number of
+    /// steps between I/O operations.
+    pub fn set_loop_detection(&mut self, enabled: bool) {
+        self.loop_detector = if enabled {
+            Some(LoopDetector::new())
+        } else {
+            None
+        };
+    }
+
+    /// Turns recording of an undo log on or off. When it is on, every step can be undone
+    /// with [`PietProgram::step_back`]. This costs memory proportional to the number of
+    /// steps executed.
+    pub fn set_history(&mut self, enabled: bool) {
+        self.history = if enabled {
+            Some(History::new(None))
+        } else {
+            None
+        };
+    }
+
+    /// Turns on an undo log that keeps only the most recent `limit` steps, so that its
+    /// memory use stays bounded however long the program runs. Older steps can't be undone.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history = Some(History::new(Some(limit)));
+    }
+
+    /// The most steps the undo log keeps, or `None` if it keeps them all or is off.
+    pub fn history_limit(&self) -> Option<usize> {
+        self.history.as_ref().and_then(|history| history.limit())
+    }
+
+    /// The number of steps that can be undone.
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, |history| history.len())
+    }
+
+    /// Starts writing a trace of every step to `trace`, beginning with its header, or stops
+    /// tracing if `trace` is `None`.
+    pub fn set_trace(&mut self, trace: Option<TraceWriter>) -> Result<()> {
+        self.trace = trace;
+        if let Some(trace) = self.trace.as_mut() {
+            trace.write_header(&TraceHeader {
+                version: TRACE_VERSION,
+                grid_hash: snapshot::grid_hash(&self.grid),
+            })?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn trace_mut(&mut self) -> Option<&mut TraceWriter> {
+        self.trace.as_mut()
+    }
+
+    /// Turns counting of block entries and moves between blocks on or off (see
+    /// [`Profile`]). Turning it on starts the counts from zero.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profile = if enabled {
+            let mut profile = Profile::new(self.graph.blocks().len());
+            profile.enter(self.current_block().id);
+            Some(profile)
+        } else {
+            None
+        };
+    }
+
+    /// The counts gathered since profiling was turned on, if it is on.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// The breakpoints that are set, with their numbers.
+    pub fn breakpoints(&self) -> &[(usize, Breakpoint)] {
+        &self.breakpoints
+    }
+
+    /// Sets a breakpoint and returns its number. Running the program stops after any
+    /// step that hits it.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> Result<usize> {
+        if let Breakpoint::Codel(codel) | Breakpoint::Block(codel) = breakpoint {
+            if !self.in_bounds(codel) {
+                return Err(PietError::runtime(format!(
+                    "breakpoint codel ({}, {}) is outside the program",
+                    codel.0, codel.1
+                )));
+            }
+        }
+        let number = self.next_breakpoint;
+        self.next_breakpoint += 1;
+        self.breakpoints.push((number, breakpoint));
+        Ok(number)
+    }
+
+    /// Removes the breakpoint with the given number, returning it if there was one.
+    pub fn remove_breakpoint(&mut self, number: usize) -> Option<Breakpoint> {
+        let index = self.breakpoints.iter().position(|(n, _)| *n == number)?;
+        Some(self.breakpoints.remove(index).1)
+    }
+
+    /// The number of input bytes consumed by commands so far.
+    pub fn input_position(&self) -> usize {
+        self.input_read - self.pending_input.len()
+    }
+
+    /// The number of output bytes written by commands so far.
+    pub fn output_position(&self) -> usize {
+        self.output_written
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    pub fn graph(&self) -> &BlockGraph {
+        &self.graph
+    }
+
+    pub fn direction_pointer(&self) -> Direction {
+        self.direction_pointer
+    }
+
+    pub fn codel_chooser(&self) -> CodelChooser {
+        self.codel_chooser
+    }
+
+    /// The codel the interpreter is currently on, as `(x, y)`.
+    pub fn position(&self) -> (i32, i32) {
+        self.position
+    }
+
+    /// Input that was read ahead but has not been consumed by a command yet, next byte last.
+    pub fn pending_input(&self) -> &[u8] {
+        &self.pending_input
+    }
+
+    /// The color block the interpreter is currently in.
+    pub fn current_block(&self) -> BlockInfo {
+        let block = self.graph.block_at(self.position);
+        BlockInfo {
+            id: block.id,
+            position: self.position,
+            color: block.color,
+            size: block.size(),
+        }
+    }
+
+    /// All codels of the color block the interpreter is currently in.
+    pub fn current_block_codels(&self) -> &[(i32, i32)] {
+        &self.graph.block_at(self.position).codels
+    }
+
+    pub fn io(&self) -> &I {
+        &self.io
+    }
+
+    pub fn io_mut(&mut self) -> &mut I {
+        &mut self.io
+    }
+
+    /// Consumes the program, returning its I/O (e.g. to inspect a `BufferIo`'s output).
+    pub fn into_io(self) -> I {
+        self.io
+    }
+
     pub fn get_color(&self, position: &(i32, i32)) -> PietColor {
         self.grid[position.1 as usize][position.0 as usize]
     }
 
-    pub fn get_current_value(&self) -> i32 {
+    pub fn get_current_value(&self) -> usize {
         self.current_value
     }
 
     pub fn toggle_codel_chooser(&mut self) {
-        self.codel_chooser = match self.codel_chooser {
-            Direction::Right => Direction::Left,
-            Direction::Left => Direction::Right,
-            _ => panic!("Invalid codel chooser: {:?}", &self.codel_chooser),
-        };
+        self.codel_chooser = self.codel_chooser.toggled();
     }
 
     pub fn move_pointer_clockwise(&mut self) {
-        self.direction_pointer = match self.direction_pointer {
-            Direction::Right => Direction::Down,
-            Direction::Down => Direction::Left,
-            Direction::Left => Direction::Up,
-            Direction::Up => Direction::Right,
-        };
+        self.direction_pointer = self.direction_pointer.clockwise();
     }
 
     pub fn move_pointer_anticlockwise(&mut self) {
@@ -72,107 +325,585 @@ impl PietProgram {
         };
     }
 
-    pub fn execute(&mut self, output_file: Option<String>, max_steps: i32) {
-        match output_file {
-            Some(file) => {
-                self.run(&mut Some(Translator::new(file)), max_steps);
-            }
-            None => {
-                self.run(&mut None, max_steps);
-            }
+    /// Captures the program's complete execution state, so that it can be resumed later
+    /// with [`PietProgram::restore`].
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            grid_hash: snapshot::grid_hash(&self.grid),
+            stack: self.stack.values().iter().map(|v| v.to_string()).collect(),
+            direction_pointer: self.direction_pointer,
+            codel_chooser: self.codel_chooser,
+            position: self.position,
+            steps: self.steps,
+            terminated: self.terminated,
+            pending_input: self.pending_input.clone(),
+            input_read: self.input_read,
         }
     }
 
-    fn run(&mut self, translator: &mut Option<Translator>, max_steps: i32) {
-        let mut terminate = false;
+    /// Puts the program back into the state captured by `snapshot`.
+    ///
+    /// Fails if the snapshot was taken from a different grid, or holds values this
+    /// program's value type can't represent. The program's I/O is left alone: it is up to
+    /// the caller to make sure it continues where the snapshotted program left off, e.g. by
+    /// skipping the first `input_read` bytes of a file.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<()> {
+        let grid_hash = snapshot::grid_hash(&self.grid);
+        if snapshot.grid_hash != grid_hash {
+            return Err(PietError::Snapshot(format!(
+                "snapshot belongs to a different image (grid hash {}, expected {})",
+                snapshot.grid_hash, grid_hash
+            )));
+        }
+        if !self.in_bounds(snapshot.position) {
+            return Err(PietError::Snapshot(format!(
+                "position ({}, {}) is outside the program",
+                snapshot.position.0, snapshot.position.1
+            )));
+        }
+        if snapshot.pending_input.len() > snapshot.input_read {
+            return Err(PietError::Snapshot(
+                "more pending input than input read".to_string(),
+            ));
+        }
+        let mut stack = Stack::new();
+        for value in &snapshot.stack {
+            let value = value.parse::<V>().map_err(|_| {
+                PietError::Snapshot(format!("stack value {} is out of range", value))
+            })?;
+            stack.push(value);
+        }
 
-        if self.get_color(&self.position).name == ColorName::White {
-            self.glide();
+        self.stack = stack;
+        self.direction_pointer = snapshot.direction_pointer;
+        self.codel_chooser = snapshot.codel_chooser;
+        self.position = snapshot.position;
+        self.current_value = self.graph.block_at(self.position).size();
+        self.steps = snapshot.steps;
+        self.terminated = snapshot.terminated;
+        self.pending_input = snapshot.pending_input.clone();
+        self.input_read = snapshot.input_read;
+        if let Some(detector) = self.loop_detector.as_mut() {
+            detector.reset();
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.clear();
         }
+        Ok(())
+    }
+
+    // Computes `a op b` according to the arithmetic mode, recording whether it overflowed.
+    pub fn arithmetic(&mut self, op: Operation, a: &V, b: &V) -> Result<V> {
+        if let Some(result) = a.checked(op, b) {
+            // An unbounded value type never overflows, but in promote mode we still note
+            // when a fixed-width implementation would have.
+            if self.arithmetic_mode == ArithmeticMode::Promote
+                && a.to_i64().is_some()
+                && b.to_i64().is_some()
+                && result.to_i64().is_none()
+            {
+                self.overflowed = true;
+                debug!("Overflow: {} {:?} {} promoted to {}", a, op, b, result);
+            }
+            return Ok(result);
+        }
+        self.overflowed = true;
+        let result = match self.arithmetic_mode {
+            ArithmeticMode::Wrapping => a.wrapping(op, b),
+            ArithmeticMode::Saturating => a.saturating(op, b),
+            ArithmeticMode::Trapping => {
+                return Err(PietError::runtime(format!(
+                    "Integer overflow: {} {:?} {}",
+                    a, op, b
+                )))
+            }
+            ArithmeticMode::Promote => {
+                return Err(PietError::runtime(format!(
+                    "Integer overflow: {} {:?} {} cannot be promoted without a bignum stack",
+                    a, op, b
+                )))
+            }
+        };
+        debug!(
+            "Overflow: {} {:?} {} gave {} ({} arithmetic)",
+            a, op, b, result, self.arithmetic_mode
+        );
+        Ok(result)
+    }
 
-        let mut steps = 0;
+    // Reads one UTF-8 encoded character from the input and returns its code point.
+    pub fn read_char(&mut self) -> Result<Option<V>> {
+        let first = match self.next_input_byte()? {
+            Some(byte) => byte,
+            None => return Ok(None),
+        };
+        let len = match first {
+            0x00..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        let mut bytes = vec![first];
+        while bytes.len() < len {
+            match self.next_input_byte()? {
+                Some(byte) => bytes.push(byte),
+                None => break,
+            }
+        }
+        match std::str::from_utf8(&bytes) {
+            Ok(s) => Ok(s.chars().next().and_then(|c| V::from_i64(c as i64))),
+            // Not valid UTF-8, fall back to the raw byte value.
+            Err(_) => Ok(V::from_i64(first as i64)),
+        }
+    }
 
+    // Reads a (possibly signed) decimal number from the input, skipping leading whitespace.
+    // The first character after the number is left in the input.
+    // Input that isn't a number is logged and ignored.
+    pub fn read_number(&mut self) -> Result<Option<V>> {
+        let mut text = String::new();
         loop {
-            // check to see if we've terminated the program
-            // which only happens if we've reached a black codel or an edge and we've
-            // tried to move 8 times and failed. (See encounter_edge)
-            if terminate {
+            match self.next_input_byte()? {
+                None => return Ok(None),
+                Some(byte) if byte.is_ascii_whitespace() => continue,
+                Some(byte) => {
+                    text.push(byte as char);
+                    break;
+                }
+            }
+        }
+        while let Some(byte) = self.next_input_byte()? {
+            if byte.is_ascii_digit() {
+                text.push(byte as char);
+            } else {
+                self.pending_input.push(byte);
                 break;
             }
-
-            // Move our position to the next codel.
-            // If we're not able to move to the next codel, we've reached an edge or a black codel.
-            if self.step().is_err() {
-                // encounter_edge returns true if we've tried to move 8 times and failed.
-                terminate = self.encounter_edge();
-                continue;
+        }
+        match text.parse::<V>() {
+            Ok(number) => Ok(Some(number)),
+            Err(_) => {
+                error!("Failed to parse input {:?} as number", text);
+                Ok(None)
             }
-            self.current_value = self.get_codels().len() as i32;
-            // Get the color of the current codel
-            let current_color = self.get_color(&self.position);
-
-            // Get the color of the next codel
-            let next_pos = self.get_next_position().unwrap();
-            let next_color = self.get_color(&next_pos);
-
-            // check if white
-            if next_color.name == ColorName::White {
-                self.glide();
-                steps += 1;
-                continue;
+        }
+    }
+
+    pub fn write_char(&mut self, value: &V) -> Result<()> {
+        let c = match value
+            .to_i64()
+            .and_then(|v| u32::try_from(v).ok())
+            .and_then(char::from_u32)
+        {
+            Some(c) => c,
+            None => {
+                debug!(
+                    "Attempted to output invalid character: {}. Ignoring.",
+                    value
+                );
+                return Ok(());
             }
+        };
+        let mut buffer = [0; 4];
+        self.write_output(c.encode_utf8(&mut buffer).as_bytes())
+    }
 
-            // Get the difference in lightness and hue between the current and next codels
-            let lightness_difference = current_color.lightness_difference(&next_color);
-            let hue_difference = current_color.hue_difference(&next_color);
-            // Get the command for the current and next codels
-            let command = Command::get_command(lightness_difference, hue_difference);
-
-            trace!(
-                "Step {} (CC: {:?} DP: {:?} - {:?} @ ({}, {}) -> {:?} @ ({}, {}))",
-                steps,
-                self.codel_chooser,
-                self.direction_pointer,
-                current_color.name,
-                self.position.0,
-                self.position.1,
-                next_color.name,
-                next_pos.0,
-                next_pos.1
-            );
-            self.position = next_pos;
+    pub fn write_number(&mut self, value: &V) -> Result<()> {
+        self.write_output(value.to_string().as_bytes())
+    }
+
+    fn write_output(&mut self, bytes: &[u8]) -> Result<()> {
+        self.io.write_bytes(bytes)?;
+        self.output_written += bytes.len();
+        if let Some(trace) = self.trace.as_mut() {
+            trace.output.extend_from_slice(bytes);
+        }
+        Ok(())
+    }
 
-            if let Some(translator) = translator.as_mut() {
-                translator.add_command(&command, self);
+    fn next_input_byte(&mut self) -> Result<Option<u8>> {
+        if let Some(byte) = self.pending_input.pop() {
+            return Ok(Some(byte));
+        }
+        let byte = self.io.read_byte()?;
+        if let Some(byte) = byte {
+            self.input_read += 1;
+            if let Some(entry) = self.recording.as_mut() {
+                entry.input.push(byte);
             }
-            command.execute(self);
-            trace!(
-                "Stack (len {}): {}\n",
-                self.stack.len(),
-                self.stack.to_string()
-            );
-
-            if steps >= max_steps && max_steps != -1 {
+        }
+        Ok(byte)
+    }
+
+    /// Runs the program until it terminates, hits a breakpoint or `max_steps` steps have
+    /// been executed (-1 for no limit).
+    pub fn execute(&mut self, max_steps: i64) -> Result<StopReason> {
+        let mut reason = StopReason::Terminated;
+        while !self.terminated {
+            if u64::try_from(max_steps).is_ok_and(|max| self.steps >= max) {
                 error!("Exceeded maximum step count.");
+                reason = StopReason::Stepped;
+                break;
+            }
+            let outcome = self.step_once()?;
+            if let Some(number) = outcome.breakpoint {
+                info!("Hit breakpoint {} at step {}.", number, outcome.step);
+                reason = StopReason::Breakpoint(number);
                 break;
+            }
+        }
+        self.io.flush()?;
+        if let Some(trace) = self.trace.as_mut() {
+            trace.flush()?;
+        }
+        Ok(reason)
+    }
+
+    /// Moves the interpreter from its current color block to the next one, executing the
+    /// command given by the color change (if any), and reports what happened.
+    ///
+    /// Once the program has terminated, further calls do nothing and return an outcome
+    /// with `terminated` set.
+    pub fn step_once(&mut self) -> Result<StepOutcome> {
+        if self.history.is_some() && !self.terminated {
+            self.recording = Some(self.undo_entry());
+        }
+        let was_terminated = self.terminated;
+        let depth = self.stack.len();
+        let result = self.advance().and_then(|mut outcome| {
+            if !was_terminated {
+                self.trace_step(&outcome, depth)?;
+                if let (Some(profile), Some(to)) = (self.profile.as_mut(), outcome.to) {
+                    profile.record(outcome.from.id, to.id);
+                }
+            }
+            if !outcome.terminated {
+                self.detect_loop(&outcome)?;
+                if outcome.breakpoint.is_none() {
+                    outcome.breakpoint = self.location_breakpoint();
+                }
+            }
+            Ok(outcome)
+        });
+        // Steps that fail are recorded too, so that they can be looked into by stepping back.
+        if let (Some(entry), Some(history)) = (self.recording.take(), self.history.as_mut()) {
+            history.push(entry);
+        }
+        result
+    }
+
+    /// Undoes the most recent step recorded since history was turned on (see
+    /// [`PietProgram::set_history`]). Input the step consumed will be read again; output it
+    /// wrote can't be taken back, but [`PietProgram::output_position`] is moved back.
+    /// Returns false if there is no step to undo.
+    pub fn step_back(&mut self) -> bool {
+        let entry = match self.history.as_mut().and_then(|history| history.pop()) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        self.position = entry.position;
+        self.direction_pointer = entry.direction_pointer;
+        self.codel_chooser = entry.codel_chooser;
+        self.steps = entry.steps;
+        self.terminated = entry.terminated;
+        self.current_value = self.graph.block_at(self.position).size();
+        while self.stack.len() > entry.stack_kept {
+            self.stack.pop();
+        }
+        for value in entry.stack_removed {
+            self.stack.push(value);
+        }
+        // The bytes the step read go back in front of the input, after what was already
+        // read ahead.
+        let mut pending_input = entry.input.into_iter().rev().collect::<Vec<u8>>();
+        pending_input.extend(entry.pending_input);
+        self.pending_input = pending_input;
+        self.output_written = entry.output_position;
+        if let Some(detector) = self.loop_detector.as_mut() {
+            detector.reset();
+        }
+        trace!("Stepped back to step {}.", self.steps);
+        true
+    }
+
+    // The first of the breakpoints set now that the most recent step in the history hits,
+    // given that the program is in the state that step left it in. Breakpoints set or
+    // removed since the step was taken count as they are now.
+    pub(crate) fn last_breakpoint(&self) -> Option<usize> {
+        let entry = self.history.as_ref()?.last()?;
+        let depth = entry.stack_kept + entry.stack_removed.len();
+        let command = entry
+            .command
+            .and_then(|(command, dp, cc)| self.command_breakpoint(command, depth, (dp, cc)));
+        command.or_else(|| {
+            if self.terminated {
+                None
             } else {
-                steps += 1;
+                self.location_breakpoint()
             }
+        })
+    }
+
+    // Everything needed to undo the next step, except the stack, which depends on the
+    // command and is filled in by `record_stack`.
+    fn undo_entry(&self) -> UndoEntry<V> {
+        UndoEntry {
+            position: self.position,
+            direction_pointer: self.direction_pointer,
+            codel_chooser: self.codel_chooser,
+            steps: self.steps,
+            terminated: self.terminated,
+            stack_kept: self.stack.len(),
+            stack_removed: Vec::new(),
+            pending_input: self.pending_input.clone(),
+            input: Vec::new(),
+            output_position: self.output_written,
+            command: None,
         }
-        // flush the translator
-        if let Some(translator) = translator.as_mut() {
-            translator.flush();
+    }
+
+    // Saves the part of the stack `command` can change into the undo entry being recorded.
+    fn record_stack(&mut self, command: Command) {
+        if self.recording.is_none() {
+            return;
+        }
+        let values = self.stack.values();
+        // Every command but roll works on at most the top two values.
+        let touched = match command {
+            Command::Roll => match values.len().checked_sub(2).map(|i| values[i].to_i64()) {
+                Some(Some(depth)) if depth < 0 => 2,
+                Some(Some(depth)) => {
+                    usize::try_from(depth).map_or(usize::MAX, |d| d.saturating_add(2))
+                }
+                _ => values.len(),
+            },
+            _ => 2,
+        };
+        let kept = values.len().saturating_sub(touched);
+        let removed = values[kept..].to_vec();
+        if let Some(entry) = self.recording.as_mut() {
+            entry.stack_kept = kept;
+            entry.stack_removed = removed;
+            entry.command = Some((command, self.direction_pointer, self.codel_chooser));
         }
     }
 
-    fn next_is_edge(&self) -> bool {
-        let next_pos = self.get_next_position();
-        next_pos.is_none()
-            || next_pos.unwrap().0 < 0
-            || next_pos.unwrap().0 >= self.grid[0].len() as i32
-            || next_pos.unwrap().1 < 0
-            || next_pos.unwrap().1 >= self.grid.len() as i32
-            || self.get_color(&next_pos.unwrap()).name == ColorName::Black
+    // Writes the trace line of a step, given the stack depth from before it.
+    fn trace_step(&mut self, outcome: &StepOutcome, depth: usize) -> Result<()> {
+        let output = match self.trace.as_mut() {
+            Some(trace) => std::mem::take(&mut trace.output),
+            None => return Ok(()),
+        };
+        let block = |info: &BlockInfo| {
+            let (x, y) = self.graph.block(info.id).codels[0];
+            TraceBlock {
+                x,
+                y,
+                color: info.color.name,
+            }
+        };
+        let input = match outcome.command {
+            Some(Command::InNumber | Command::InChar) if self.stack.len() > depth => {
+                self.stack.peek().map(|value| value.to_string())
+            }
+            _ => None,
+        };
+        let step = TraceStep {
+            step: outcome.step,
+            from: block(&outcome.from),
+            to: outcome.to.as_ref().map(block),
+            direction_pointer: self.direction_pointer,
+            codel_chooser: self.codel_chooser,
+            command: outcome.command,
+            depth: self.stack.len(),
+            top: self
+                .stack
+                .values()
+                .iter()
+                .rev()
+                .take(TOP_VALUES)
+                .map(|value| value.to_string())
+                .collect(),
+            input,
+            output: (!output.is_empty()).then(|| String::from_utf8_lossy(&output).into_owned()),
+            overflowed: outcome.overflowed,
+        };
+        match self.trace.as_mut() {
+            Some(trace) => trace.write_step(step),
+            None => Ok(()),
+        }
+    }
+
+    // The first breakpoint on the codel or block the interpreter is now in.
+    fn location_breakpoint(&self) -> Option<usize> {
+        let block = self.graph.block_at(self.position).id;
+        self.breakpoints
+            .iter()
+            .find(|(_, breakpoint)| match *breakpoint {
+                Breakpoint::Codel(codel) => codel == self.position,
+                Breakpoint::Block(codel) => self.graph.block_at(codel).id == block,
+                _ => false,
+            })
+            .map(|(number, _)| *number)
+    }
+
+    // The first breakpoint hit by executing `command`, given the stack depth, DP and CC
+    // from before it ran.
+    fn command_breakpoint(
+        &self,
+        command: Command,
+        depth: usize,
+        pointer: (Direction, CodelChooser),
+    ) -> Option<usize> {
+        self.breakpoints
+            .iter()
+            .find(|(_, breakpoint)| match *breakpoint {
+                Breakpoint::Command(c) => c == command,
+                Breakpoint::DepthAbove(n) => depth <= n && self.stack.len() > n,
+                Breakpoint::DepthBelow(n) => depth >= n && self.stack.len() < n,
+                Breakpoint::TopEquals(value) => self
+                    .stack
+                    .peek()
+                    .is_some_and(|top| top.to_i64() == Some(value)),
+                Breakpoint::PointerChange => {
+                    matches!(command, Command::Pointer | Command::Switch)
+                        && pointer != (self.direction_pointer, self.codel_chooser)
+                }
+                Breakpoint::Codel(_) | Breakpoint::Block(_) => false,
+            })
+            .map(|(number, _)| *number)
+    }
+
+    fn detect_loop(&mut self, outcome: &StepOutcome) -> Result<()> {
+        let detector = match self.loop_detector.as_mut() {
+            Some(detector) => detector,
+            None => return Ok(()),
+        };
+        if outcome.command.is_some_and(|c| c.performs_io()) {
+            detector.reset();
+            return Ok(());
+        }
+        let state = MachineState {
+            block: self.graph.block_at(self.position).id,
+            direction_pointer: self.direction_pointer,
+            codel_chooser: self.codel_chooser,
+            stack: self.stack.values().to_vec(),
+            input_position: self.input_read - self.pending_input.len(),
+        };
+        if let Some(cycle) = detector.record(state) {
+            let mut blocks = Vec::new();
+            for state in cycle {
+                let origin = self.graph.block(state.block).codels[0];
+                if !blocks.contains(&origin) {
+                    blocks.push(origin);
+                }
+            }
+            return Err(PietError::InfiniteLoop {
+                step: self.steps,
+                cycle_length: cycle.len(),
+                blocks,
+            });
+        }
+        Ok(())
+    }
+
+    fn advance(&mut self) -> Result<StepOutcome> {
+        let mut outcome = StepOutcome {
+            step: self.steps,
+            command: None,
+            from: self.current_block(),
+            to: None,
+            crossed_white: false,
+            recoveries: Vec::new(),
+            terminated: self.terminated,
+            overflowed: false,
+            breakpoint: None,
+        };
+        if self.terminated {
+            return Ok(outcome);
+        }
+
+        // A program that starts on white slides to its first color block.
+        if self.steps == 0 && self.get_color(&self.position).name == ColorName::White {
+            outcome.crossed_white = true;
+            self.steps += 1;
+            if self.slide(&mut outcome.recoveries) {
+                self.terminated = true;
+                outcome.terminated = true;
+            } else {
+                outcome.to = Some(self.current_block());
+            }
+            return Ok(outcome);
+        }
+
+        // Move our position to the exit codel of the current block.
+        // If we're not able to move to the next codel, we've reached an edge or a black codel.
+        if self.step().is_err() {
+            // encounter_edge returns true if we've tried to move 8 times and failed,
+            // in which case the program terminates.
+            if self.encounter_edge(&mut outcome.recoveries) {
+                self.terminated = true;
+                outcome.terminated = true;
+                return Ok(outcome);
+            }
+        }
+        self.current_value = self.graph.block_at(self.position).size();
+        // Get the color of the current codel
+        let current_color = self.get_color(&self.position);
+
+        // Get the color of the next codel
+        let next_pos = self.get_next_position().unwrap();
+        let next_color = self.get_color(&next_pos);
+
+        // check if white
+        if next_color.name == ColorName::White {
+            self.position = next_pos;
+            outcome.crossed_white = true;
+            self.steps += 1;
+            if self.slide(&mut outcome.recoveries) {
+                self.terminated = true;
+                outcome.terminated = true;
+            } else {
+                outcome.to = Some(self.current_block());
+            }
+            return Ok(outcome);
+        }
+
+        // Get the difference in lightness and hue between the current and next codels
+        let lightness_difference = current_color.lightness_difference(&next_color);
+        let hue_difference = current_color.hue_difference(&next_color);
+        // Get the command for the current and next codels
+        let command = Command::get_command(lightness_difference, hue_difference)
+            .map_err(|e| e.at(self.position))?;
+
+        trace!(
+            "Step {} (CC: {:?} DP: {:?} - {:?} @ ({}, {}) -> {:?} @ ({}, {}))",
+            self.steps,
+            self.codel_chooser,
+            self.direction_pointer,
+            current_color.name,
+            self.position.0,
+            self.position.1,
+            next_color.name,
+            next_pos.0,
+            next_pos.1
+        );
+        self.position = next_pos;
+        outcome.to = Some(self.current_block());
+
+        let depth = self.stack.len();
+        let pointer = (self.direction_pointer, self.codel_chooser);
+        self.record_stack(command);
+        self.overflowed = false;
+        command.execute(self).map_err(|e| e.at(next_pos))?;
+        outcome.overflowed = self.overflowed;
+        outcome.breakpoint = self.command_breakpoint(command, depth, pointer);
+        trace!("Stack (len {}): {}\n", self.stack.len(), self.stack);
+        outcome.command = Some(command);
+        self.steps += 1;
+        Ok(outcome)
     }
 
     // Black colour blocks and the edges of the program restrict program flow.
@@ -180,7 +911,7 @@ impl PietProgram {
     // The interpreter then attempts to move from its current block again. If it fails a second time, the DP is moved clockwise one step.
     // These attempts are repeated, with the CC and DP being changed between alternate attempts.
     // If after eight attempts the interpreter cannot leave its current colour block, there is no way out and the program terminates.
-    fn encounter_edge(&mut self) -> bool {
+    fn encounter_edge(&mut self, recoveries: &mut Vec<Recovery>) -> bool {
         trace!("Encountered edge at position {:?}.", self.position);
         let mut attempts = 0;
         loop {
@@ -193,9 +924,11 @@ impl PietProgram {
                     }
                     if attempts % 2 == 0 {
                         self.toggle_codel_chooser();
+                        recoveries.push(Recovery::ToggledCodelChooser(self.codel_chooser));
                         trace!("Toggled codel chooser to {:?}.", self.codel_chooser)
                     } else {
                         self.move_pointer_clockwise();
+                        recoveries.push(Recovery::RotatedPointer(self.direction_pointer));
                         trace!("Moved pointer clockwise to {:?}.", self.direction_pointer)
                     }
                     attempts += 1;
@@ -205,142 +938,80 @@ impl PietProgram {
     }
 
     // White color blocks act like blank spaces.
-    // When the DP encounters a white block, it will simply go through it and move on to the next color block.
-    // AKA it glides through all white blocks without executing any commands.
-    fn glide(&mut self) {
+    // When the interpreter moves into white, it slides through the white codels in the
+    // direction of the DP until it reaches a colored codel, without executing any commands.
+    // If it slides into a black codel or an edge, it toggles the CC, rotates the DP clockwise
+    // and carries on sliding from the white codel it's on.
+    // If it ever gets back to a white codel it has already been on with the same DP, it is
+    // going round in circles and the program terminates. Returns true in that case.
+    fn slide(&mut self, recoveries: &mut Vec<Recovery>) -> bool {
+        let start = self.position;
+        let mut visited = HashSet::new();
         loop {
-            let next_pos = self.get_next_position();
-            if next_pos.is_none() {
-                self.encounter_edge();
+            if !visited.insert((self.position, self.direction_pointer)) {
+                trace!(
+                    "White path revisited ({}, {}) heading {:?}. Terminating program.",
+                    self.position.0,
+                    self.position.1,
+                    self.direction_pointer
+                );
+                return true;
             }
-            let next_pos = next_pos.unwrap();
-            if next_pos.0 < 0
-                || next_pos.0 >= self.grid[0].len() as i32
-                || next_pos.1 < 0
-                || next_pos.1 >= self.grid.len() as i32
-            {
-                break;
+            let (dx, dy) = self.direction_pointer.to_vector();
+            let next_pos = (self.position.0 + dx, self.position.1 + dy);
+            if !self.in_bounds(next_pos) || self.get_color(&next_pos).name == ColorName::Black {
+                self.toggle_codel_chooser();
+                self.move_pointer_clockwise();
+                recoveries.push(Recovery::ToggledCodelChooser(self.codel_chooser));
+                recoveries.push(Recovery::RotatedPointer(self.direction_pointer));
+                trace!(
+                    "Slide blocked at ({}, {}). Toggled codel chooser to {:?} and moved pointer clockwise to {:?}.",
+                    self.position.0,
+                    self.position.1,
+                    self.codel_chooser,
+                    self.direction_pointer
+                );
+                continue;
             }
-            let next_color = self.get_color(&next_pos);
             self.position = next_pos;
-            if next_color.name != ColorName::White {
+            if self.get_color(&next_pos).name != ColorName::White {
                 break;
             }
         }
         trace!(
-            "White codels(s) crossed, glided from ({}, {}) to ({}, {}). Increasing step by 1. \n",
+            "White codel(s) crossed, slid from ({}, {}) to ({}, {}).",
+            start.0,
+            start.1,
             self.position.0,
-            self.position.1,
-            self.get_next_position().unwrap().0,
-            self.get_next_position().unwrap().1
+            self.position.1
         );
+        false
     }
 
-    fn step(&mut self) -> Result<(), ()> {
-        // Given all the codels in the color block, get all codels in the current color block that are on the furthest edge in the direction of the DP.
-        // For example, if the DP is facing right, get all codels on the FARTHEST right edge of the color block.
-        let all_codels = self.get_codels();
-
-        // Find max x or y values depending on DP direction
-        let max: i32 = match self.direction_pointer {
-            Direction::Right => all_codels.iter().map(|c| c.0).max().unwrap(),
-            Direction::Down => all_codels.iter().map(|c| c.1).max().unwrap(),
-            Direction::Left => all_codels.iter().map(|c| c.0).min().unwrap(),
-            Direction::Up => all_codels.iter().map(|c| c.1).min().unwrap(),
-        };
-
-        // Get every codel whose x or y value is equal to the max value
-        let edge_codels = all_codels
-            .iter()
-            .filter(|c| match self.direction_pointer {
-                Direction::Right => c.0 == max,
-                Direction::Down => c.1 == max,
-                Direction::Left => c.0 == max,
-                Direction::Up => c.1 == max,
-            })
-            .collect::<Vec<&(i32, i32)>>();
+    fn in_bounds(&self, position: (i32, i32)) -> bool {
+        position.0 >= 0
+            && position.0 < self.grid[0].len() as i32
+            && position.1 >= 0
+            && position.1 < self.grid.len() as i32
+    }
 
-        // If there is more than one codel on the edge, choose the one that is furthest in the direction of the CC
-        // use the choose_codel method to get the direction of the codel chooser, since it's relative to the DP.
-        if edge_codels.len() == 1 {
-            self.position = *edge_codels[0];
-        } else {
-            let codel = edge_codels
-                .iter()
-                .max_by_key(|c| match self.choose_codel() {
-                    Direction::Right => c.0,
-                    Direction::Down => c.1,
-                    Direction::Left => -c.0,
-                    Direction::Up => -c.1,
-                })
-                .unwrap();
-
-            self.position = **codel;
-        }
+    fn step(&mut self) -> std::result::Result<(), ()> {
+        // Move to the codel of the current block that is furthest in the direction of the DP,
+        // using the CC to break ties. The block graph already knows which one that is.
+        let exit = *self
+            .graph
+            .block_at(self.position)
+            .exit(self.direction_pointer, self.codel_chooser);
+        self.position = exit.codel;
 
         // Check if the next position is an edge or a black block
-        if self.next_is_edge() {
+        if exit.next.is_none() {
             return Err(());
         }
 
         Ok(())
     }
 
-    // given our DP and CC, we pick which codel to choose based on the direction of the DP and CC
-    fn choose_codel(&self) -> Direction {
-        match (self.direction_pointer, self.codel_chooser) {
-            (Direction::Right, Direction::Right) => Direction::Down,
-            (Direction::Right, Direction::Left) => Direction::Up,
-            (Direction::Down, Direction::Right) => Direction::Left,
-            (Direction::Down, Direction::Left) => Direction::Right,
-            (Direction::Left, Direction::Right) => Direction::Up,
-            (Direction::Left, Direction::Left) => Direction::Down,
-            (Direction::Up, Direction::Right) => Direction::Right,
-            (Direction::Up, Direction::Left) => Direction::Left,
-            _ => panic!(
-                "Invalid direction_pointer: {:?} or codel_chooser: {:?}",
-                &self.direction_pointer, &self.codel_chooser
-            ),
-        }
-    }
-
-    // returns all codels in the current color block
-    fn get_codels(&self) -> Vec<(i32, i32)> {
-        let mut codels = Vec::new();
-        let mut visited = vec![vec![false; self.grid[0].len()]; self.grid.len()];
-        let mut stack = vec![self.position];
-        let current_color = self.get_color(&self.position); // Get the color of the current position
-        while let Some(current) = stack.pop() {
-            if visited[current.1 as usize][current.0 as usize] {
-                continue;
-            }
-            visited[current.1 as usize][current.0 as usize] = true;
-            if self.get_color(&current) == current_color {
-                codels.push(current);
-            }
-            for direction in &[
-                Direction::Right,
-                Direction::Down,
-                Direction::Left,
-                Direction::Up,
-            ] {
-                let next_position = (
-                    current.0 + direction.to_vector().0,
-                    current.1 + direction.to_vector().1,
-                );
-                if next_position.0 >= 0
-                    && next_position.0 < self.grid[0].len() as i32
-                    && next_position.1 >= 0
-                    && next_position.1 < self.grid.len() as i32
-                    && self.get_color(&next_position) == current_color
-                {
-                    stack.push(next_position);
-                }
-            }
-        }
-        codels
-    }
-
     fn get_next_position(&self) -> Option<(i32, i32)> {
         if self.position.0 < 0
             || self.position.0 >= self.grid[0].len() as i32
@@ -356,7 +1027,7 @@ impl PietProgram {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum Direction {
     Right,
     Down,
@@ -365,7 +1036,37 @@ pub enum Direction {
 }
 
 impl Direction {
-    fn to_vector(self) -> (i32, i32) {
+    pub const ALL: [Direction; 4] = [
+        Direction::Right,
+        Direction::Down,
+        Direction::Left,
+        Direction::Up,
+    ];
+
+    // given our DP and CC, we pick which codel to choose based on the direction of the DP and CC
+    pub fn choose_codel(self, codel_chooser: CodelChooser) -> Direction {
+        match (self, codel_chooser) {
+            (Direction::Right, CodelChooser::Right) => Direction::Down,
+            (Direction::Right, CodelChooser::Left) => Direction::Up,
+            (Direction::Down, CodelChooser::Right) => Direction::Left,
+            (Direction::Down, CodelChooser::Left) => Direction::Right,
+            (Direction::Left, CodelChooser::Right) => Direction::Up,
+            (Direction::Left, CodelChooser::Left) => Direction::Down,
+            (Direction::Up, CodelChooser::Right) => Direction::Right,
+            (Direction::Up, CodelChooser::Left) => Direction::Left,
+        }
+    }
+
+    pub fn clockwise(self) -> Direction {
+        match self {
+            Direction::Right => Direction::Down,
+            Direction::Down => Direction::Left,
+            Direction::Left => Direction::Up,
+            Direction::Up => Direction::Right,
+        }
+    }
+
+    pub fn to_vector(self) -> (i32, i32) {
         match self {
             Direction::Right => (1, 0),
             Direction::Down => (0, 1),
@@ -374,3 +1075,68 @@ impl Direction {
         }
     }
 }
+
+/// A color block, and the codel the interpreter was on when it was in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockInfo {
+    pub id: BlockId,
+    pub position: (i32, i32),
+    pub color: PietColor,
+    pub size: usize,
+}
+
+/// A change the interpreter made to the CC or DP after failing to leave a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// The CC was toggled; holds its new value.
+    ToggledCodelChooser(CodelChooser),
+    /// The DP was rotated clockwise; holds its new value.
+    RotatedPointer(Direction),
+}
+
+/// What happened during a single call to [`PietProgram::step_once`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepOutcome {
+    /// The step number, counting from 0.
+    pub step: u64,
+    /// The command that was executed, if any. Moves through white never execute one.
+    pub command: Option<Command>,
+    /// The block the interpreter started in.
+    pub from: BlockInfo,
+    /// The block the interpreter ended up in. `None` if it never left `from`.
+    pub to: Option<BlockInfo>,
+    /// Whether the move went through white codels.
+    pub crossed_white: bool,
+    /// The CC/DP changes made because the way out of `from`, or a slide through white,
+    /// was blocked, in order.
+    pub recoveries: Vec<Recovery>,
+    /// Whether the program has terminated.
+    pub terminated: bool,
+    /// Whether the command's arithmetic overflowed (see [`ArithmeticMode`]).
+    pub overflowed: bool,
+    /// The number of the breakpoint this step hit, if any.
+    pub breakpoint: Option<usize>,
+}
+
+impl StepOutcome {
+    /// Returns true if the interpreter had to change CC or DP to get where it went.
+    pub fn was_blocked(&self) -> bool {
+        !self.recoveries.is_empty()
+    }
+}
+
+// The CC can only ever point left or right, relative to the DP.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum CodelChooser {
+    Left,
+    Right,
+}
+
+impl CodelChooser {
+    pub fn toggled(self) -> CodelChooser {
+        match self {
+            CodelChooser::Right => CodelChooser::Left,
+            CodelChooser::Left => CodelChooser::Right,
+        }
+    }
+}
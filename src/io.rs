@@ -0,0 +1,173 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// The input and output streams of a Piet program.
+///
+/// The interpreter only ever reads and writes bytes through this trait; parsing numbers
+/// and encoding characters is done by the interpreter itself, so implementations stay small.
+pub trait PietIo {
+    /// Reads a single byte of input. Returns `None` at the end of input.
+    fn read_byte(&mut self) -> io::Result<Option<u8>>;
+
+    /// Writes program output.
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()>;
+
+    /// Flushes any buffered output.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: PietIo + ?Sized> PietIo for &mut T {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        (**self).read_byte()
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        (**self).write_bytes(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+}
+
+impl<T: PietIo + ?Sized> PietIo for Box<T> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        (**self).read_byte()
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        (**self).write_bytes(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+}
+
+/// Reads from the process's stdin and writes to its stdout.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdIo;
+
+impl StdIo {
+    pub fn new() -> Self {
+        StdIo
+    }
+}
+
+impl PietIo for StdIo {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        // Make sure any prompt the program printed is visible before we block on input.
+        io::stdout().flush()?;
+        let mut buffer = [0; 1];
+        match io::stdin().read(&mut buffer)? {
+            0 => Ok(None),
+            _ => Ok(Some(buffer[0])),
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        io::stdout().write_all(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+/// Takes input from an in-memory buffer and collects output in memory.
+#[derive(Debug, Default, Clone)]
+pub struct BufferIo {
+    input: Vec<u8>,
+    position: usize,
+    output: Vec<u8>,
+}
+
+impl BufferIo {
+    pub fn new(input: impl Into<Vec<u8>>) -> Self {
+        BufferIo {
+            input: input.into(),
+            position: 0,
+            output: Vec::new(),
+        }
+    }
+
+    /// Everything the program has written so far.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// The output interpreted as (lossy) UTF-8.
+    pub fn output_string(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+
+    /// The input that has not been read yet.
+    pub fn remaining_input(&self) -> &[u8] {
+        &self.input[self.position..]
+    }
+}
+
+impl PietIo for BufferIo {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.input.get(self.position).copied();
+        if byte.is_some() {
+            self.position += 1;
+        }
+        Ok(byte)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.output.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// Reads from any buffered reader and writes to any writer.
+#[derive(Debug)]
+pub struct StreamIo<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: BufRead, W: Write> StreamIo<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        StreamIo { reader, writer }
+    }
+
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+}
+
+impl<R: BufRead, W: Write> PietIo for StreamIo<R, W> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.reader.fill_buf()?.first().copied();
+        if byte.is_some() {
+            self.reader.consume(1);
+        }
+        Ok(byte)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads input from one file and writes output to another.
+pub type FileIo = StreamIo<BufReader<File>, BufWriter<File>>;
+
+impl FileIo {
+    /// Opens `input` for reading and creates (or truncates) `output` for writing.
+    pub fn open(input: impl AsRef<Path>, output: impl AsRef<Path>) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(input)?);
+        let writer = BufWriter::new(File::create(output)?);
+        Ok(StreamIo::new(reader, writer))
+    }
+}
@@ -0,0 +1,55 @@
+//! Piet interpreter and code translator.
+//!
+//! The crate can be embedded in other Rust programs. A typical run loads an
+//! image into a grid of colors, builds a [`PietProgram`] from it and executes it:
+//!
+//! ```no_run
+//! use piet_tool::{load_image, PietProgram};
+//!
+//! # fn main() -> piet_tool::Result<()> {
+//! let grid = load_image("examples/Piet_hello.png", 1)?;
+//! let mut program = PietProgram::new(grid);
+//! program.execute(10_000)?;
+//! println!("stack: {}", program.stack());
+//! # Ok(())
+//! # }
+//! ```
+
+#[macro_use]
+extern crate log;
+
+pub mod breakpoint;
+pub mod cfg;
+pub mod color;
+pub mod command;
+mod cycle;
+pub mod dap;
+pub mod debugger;
+pub mod error;
+pub mod graph;
+mod history;
+pub mod interpreter;
+pub mod io;
+pub mod loader;
+pub mod profile;
+pub mod render;
+pub mod snapshot;
+pub mod stack;
+pub mod trace;
+pub mod trace_diff;
+pub mod translator;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod value;
+
+pub use breakpoint::{Breakpoint, StopReason};
+pub use color::PietColor;
+pub use command::Command;
+pub use debugger::Debugger;
+pub use error::{PietError, Result};
+pub use interpreter::{BlockInfo, CodelChooser, Direction, PietProgram, StepOutcome};
+pub use io::{BufferIo, FileIo, PietIo, StdIo};
+pub use loader::{load_image, load_image_from_bytes, Grid};
+pub use snapshot::Snapshot;
+pub use trace::{TraceStep, TraceWriter};
+pub use value::{ArithmeticMode, PietValue};
@@ -0,0 +1,75 @@
+use image::error::{ParameterError, ParameterErrorKind};
+use image::{DynamicImage, ImageError, RgbImage};
+
+use crate::color::PietColor;
+use crate::error::{PietError, Result};
+
+/// A Piet program as a 2D grid of codels, indexed as `grid[y][x]`.
+pub type Grid = Vec<Vec<PietColor>>;
+
+/// Loads a Piet program from an image file on disk.
+pub fn load_image(path: &str, codel_size: u32) -> Result<Grid> {
+    let img = image::open(path)?;
+    grid_from_image(img, codel_size)
+}
+
+/// Loads a Piet program from an encoded image held in memory (PNG, GIF, ...).
+pub fn load_image_from_bytes(bytes: &[u8], codel_size: u32) -> Result<Grid> {
+    let img = image::load_from_memory(bytes)?;
+    grid_from_image(img, codel_size)
+}
+
+fn grid_from_image(img: DynamicImage, codel_size: u32) -> Result<Grid> {
+    let start_time = std::time::Instant::now();
+    let img: RgbImage = img.to_rgb8();
+    let (width, height) = img.dimensions();
+    if codel_size == 0 || width < codel_size || height < codel_size {
+        return Err(invalid_parameter(format!(
+            "a {}x{} image cannot be split into codels of size {}",
+            width, height, codel_size
+        )));
+    }
+    let grid_width = width / codel_size;
+    let grid_height = height / codel_size;
+    let mut result = vec![vec![PietColor::default(); grid_width as usize]; grid_height as usize];
+    for codel_y in 0..grid_height {
+        for codel_x in 0..grid_width {
+            let x = codel_x * codel_size;
+            let y = codel_y * codel_size;
+            let mut codel = vec![PietColor::default(); codel_size as usize];
+            for i in 0..codel_size {
+                let pixel = img.get_pixel(x, y + i);
+                let rgb = [pixel[0], pixel[1], pixel[2]];
+                match PietColor::from_rgb(&rgb) {
+                    Ok(color) => codel[i as usize] = color,
+                    Err(_) => {
+                        return Err(PietError::Color {
+                            x: codel_x,
+                            y: codel_y,
+                            rgb,
+                        })
+                    }
+                }
+            }
+            result[codel_y as usize][codel_x as usize] = codel[0];
+        }
+    }
+    let elapsed = start_time.elapsed();
+    debug!(
+        "Loaded image with dimensions: {}x{} in {:?}",
+        width, height, elapsed
+    );
+    debug!(
+        "Size of grid: {}x{}. Codel size: {}",
+        result[0].len(),
+        result.len(),
+        codel_size
+    );
+    Ok(result)
+}
+
+fn invalid_parameter(message: String) -> PietError {
+    PietError::Image(ImageError::Parameter(ParameterError::from_kind(
+        ParameterErrorKind::Generic(message),
+    )))
+}
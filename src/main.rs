@@ -1,31 +1,49 @@
 use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
 
-use clap::{ColorChoice, Parser};
-use interpreter::PietProgram;
+use clap::{ColorChoice, Parser, Subcommand};
+use piet_tool::io::StreamIo;
+use piet_tool::render::{self, GifOptions};
+use piet_tool::trace::{TraceReader, TraceSteps};
+use piet_tool::trace_diff::{self, Comparison};
+use piet_tool::translator::Target;
+use piet_tool::{
+    debugger, load_image, ArithmeticMode, Breakpoint, Debugger, Grid, PietError, PietIo,
+    PietProgram, PietValue, Snapshot, StdIo, StopReason, TraceWriter,
+};
 
 #[macro_use]
 extern crate log;
-extern crate pretty_env_logger;
 
 #[derive(Parser, Debug)]
 #[command(
     version,
     author,
     about = "Piet interpreter with Forth code translation.",
-    color(ColorChoice::Always)
+    color(ColorChoice::Always),
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
 )]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// The location of a valid input file
-    #[arg()]
-    input_file: String,
+    #[arg(required = true)]
+    input_file: Option<String>,
 
-    /// Translate the program to Forth code
+    /// Translate the program to Forth code (or see --target) instead of running it
     #[arg(short = 'f', long, default_value_t = false)]
     translate: bool,
 
-    /// The location of the output file (if translating)
-    #[arg(short, long, requires("translate"), default_value = "out.f")]
-    output_file: String,
+    /// The language to translate to: forth, c, rust (needs piet_tool as a dependency) or wat
+    #[arg(long, requires("translate"), default_value_t = Target::Forth)]
+    target: Target,
+
+    /// The location of the output file (if translating) [default: out.f, out.c, out.rs or out.wat]
+    #[arg(short, long, requires("translate"))]
+    output_file: Option<String>,
 
     /// Debug level (0 = error, 1 = info, 2 = debug, 3 = trace)
     #[arg(short, long, default_value_t = 0)]
@@ -35,25 +53,174 @@ struct Args {
     #[arg(short, long, default_value_t = 1)]
     codel_size: i32,
 
-    /// Max Execution Steps. (-1 for infinite.)
+    /// Read the program's input from this file instead of stdin
+    #[arg(short, long)]
+    input: Option<String>,
+
+    /// Use arbitrary-precision integers on the stack
+    #[cfg(feature = "bigint")]
+    #[arg(long, default_value_t = false)]
+    bigint: bool,
+
+    /// What to do when arithmetic overflows: wrapping, saturating, trap or promote [default: trap]. Translated programs always wrap
+    #[arg(short, long)]
+    arithmetic: Option<ArithmeticMode>,
+
+    /// Stop with an error as soon as the program provably loops forever
+    #[arg(short = 'l', long, default_value_t = false)]
+    detect_loops: bool,
+
+    /// Stop at a breakpoint (e.g. `3,4`, `block 3,4`, `command out_char`, `depth>10`,
+    /// `top=42` or `pointer`), print the interpreter state and exit. Can be repeated.
+    #[arg(short, long = "break", value_name = "BREAKPOINT")]
+    breakpoints: Vec<Breakpoint>,
+
+    /// Write a JSON line describing every step to this file
+    #[arg(long, value_name = "FILE")]
+    trace_file: Option<String>,
+
+    /// Write a PNG to this file showing how often each block was entered and the most
+    /// frequent moves between blocks
+    #[arg(long, value_name = "FILE")]
+    heatmap: Option<String>,
+
+    /// The width and height of a codel in the heatmap, in pixels
+    #[arg(long, default_value_t = 10, requires("heatmap"), value_parser = clap::value_parser!(u32).range(1..))]
+    heatmap_scale: u32,
+
+    /// Save the interpreter state to this file when execution stops
+    #[arg(long)]
+    save_state: Option<String>,
+
+    /// Resume from a state saved with --save-state
+    #[arg(long)]
+    resume: Option<String>,
+
+    /// Max Execution Steps, counted from the start of the program. (-1 for infinite.)
     #[arg(short, long, default_value_t = -1)]
-    max_steps: i32,
+    max_steps: i64,
 }
 
-pub mod color;
-pub mod command;
-pub mod interpreter;
-pub mod stack;
-pub mod translator;
+impl Args {
+    fn arithmetic(&self) -> ArithmeticMode {
+        self.arithmetic.unwrap_or(ArithmeticMode::Trapping)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Step through a program interactively
+    Debug(DebugArgs),
+    /// Watch a program run in a full-screen terminal UI
+    #[cfg(feature = "tui")]
+    Tui(DebugArgs),
+    /// Serve the Debug Adapter Protocol on stdin and stdout, for debugging from an editor
+    Dap,
+    /// Show where two traces (see --trace-file) first differ
+    TraceDiff(TraceDiffArgs),
+    /// Run a program and write an animated GIF of its run
+    RenderTrace(RenderArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct DebugArgs {
+    /// The location of a valid input file
+    input_file: String,
+
+    /// Codel Size
+    #[arg(short, long, default_value_t = 1)]
+    codel_size: i32,
+
+    /// Read the program's input from this file instead of stdin
+    #[arg(short, long)]
+    input: Option<String>,
+
+    /// What to do when arithmetic overflows: wrapping, saturating, trap or promote
+    #[arg(short, long, default_value_t = ArithmeticMode::Trapping)]
+    arithmetic: ArithmeticMode,
+
+    /// Set a breakpoint (see the main command's --break). Can be repeated.
+    #[arg(short, long = "break", value_name = "BREAKPOINT")]
+    breakpoints: Vec<Breakpoint>,
+}
+
+#[derive(clap::Args, Debug)]
+struct TraceDiffArgs {
+    /// The first trace, or image with --images
+    a: String,
+
+    /// The second trace, or image with --images
+    b: String,
+
+    /// Run two images side by side on the same input instead of reading traces
+    #[arg(long, default_value_t = false)]
+    images: bool,
+
+    /// How many steps to show before and after the first difference
+    #[arg(short = 'C', long, default_value_t = 3)]
+    context: usize,
+
+    /// Codel Size (with --images)
+    #[arg(short, long, default_value_t = 1)]
+    codel_size: i32,
+
+    /// Read the programs' input from this file instead of stdin (with --images)
+    #[arg(short, long, requires = "images")]
+    input: Option<String>,
+
+    /// Max Execution Steps for each program (with --images). (-1 for infinite.)
+    #[arg(short, long, default_value_t = -1)]
+    max_steps: i64,
+}
+
+#[derive(clap::Args, Debug)]
+struct RenderArgs {
+    /// The location of a valid input file
+    input_file: String,
+
+    /// The location of the GIF to write
+    #[arg(short, long, default_value = "run.gif")]
+    output_file: String,
+
+    /// Codel Size
+    #[arg(short, long, default_value_t = 1)]
+    codel_size: i32,
+
+    /// Read the program's input from this file (otherwise it gets no input)
+    #[arg(short, long)]
+    input: Option<String>,
+
+    /// The width and height of a codel in the GIF, in pixels
+    #[arg(short, long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    scale: u32,
+
+    /// Draw a frame every N steps
+    #[arg(short, long, default_value_t = 1, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    every: u32,
+
+    /// The first step to draw
+    #[arg(long, default_value_t = 0)]
+    from: u64,
 
-use crate::color::PietColor;
+    /// The last step to draw (-1 for until the program terminates)
+    #[arg(long, default_value_t = -1)]
+    to: i64,
+
+    /// Without --to, give up if the program hasn't terminated after this many steps
+    #[arg(short, long, default_value_t = 10_000)]
+    max_steps: u64,
+
+    /// How long each frame is shown, in milliseconds
+    #[arg(long, default_value_t = 100)]
+    delay: u32,
+
+    /// Show the stack next to the program
+    #[arg(long, default_value_t = false)]
+    stack: bool,
+}
 
 fn main() {
     let args = Args::parse();
-    let codel_size = args.codel_size;
-    let translate = args.translate;
-    let output_file = args.output_file;
-    let max_steps = args.max_steps;
     match args.debug {
         1 => env::set_var("RUST_LOG", "info"),
         2 => env::set_var("RUST_LOG", "debug"),
@@ -61,56 +228,278 @@ fn main() {
         _ => env::set_var("RUST_LOG", "error"),
     }
     pretty_env_logger::init();
-    let grid = load_image(&args.input_file, codel_size as u32);
+    if let Some(command) = &args.command {
+        let result = match command {
+            Command::Debug(debug_args) => debug(debug_args),
+            #[cfg(feature = "tui")]
+            Command::Tui(tui_args) => tui(tui_args),
+            Command::Dap => piet_tool::dap::serve(io::stdin(), io::stdout()),
+            Command::TraceDiff(diff_args) => match trace_diff(diff_args) {
+                Ok(true) => Ok(()),
+                // Like diff(1), exit with 1 when there is a difference.
+                Ok(false) => std::process::exit(1),
+                Err(e) => Err(e),
+            },
+            Command::RenderTrace(render_args) => render_trace(render_args),
+        };
+        if let Err(e) = result {
+            exit_with(e);
+        }
+        return;
+    }
+    let input_file = args
+        .input_file
+        .as_deref()
+        .expect("clap requires an input file");
+    let grid = match load_image(input_file, args.codel_size as u32) {
+        Ok(grid) => grid,
+        Err(e) => exit_with(e),
+    };
+    if args.translate {
+        // Every target wraps around on overflow, so other modes can't be translated.
+        if let Some(mode) = args
+            .arithmetic
+            .filter(|&mode| mode != ArithmeticMode::Wrapping)
+        {
+            exit_with(PietError::Translation(format!(
+                "translated programs wrap around on overflow, so they can't use -a {}",
+                mode
+            )));
+        }
+        let output_file = args
+            .output_file
+            .as_deref()
+            .unwrap_or(args.target.default_output());
+        if let Err(e) = args.target.translate(&grid, output_file) {
+            exit_with(e);
+        }
+        return;
+    }
+    let snapshot = match args.resume.as_ref().map(Snapshot::load).transpose() {
+        Ok(snapshot) => snapshot,
+        Err(e) => exit_with(e),
+    };
     let start_time = std::time::Instant::now();
 
-    let mut program = PietProgram::new(grid);
-    if translate {
-        program.execute(Some(output_file), max_steps);
+    let io: Box<dyn PietIo> = match &args.input {
+        Some(path) => match open_input(path, snapshot.as_ref()) {
+            Ok(input) => Box::new(StreamIo::new(input, io::stdout())),
+            Err(e) => {
+                error!("Failed to open input file {}", path);
+                exit_with(e.into());
+            }
+        },
+        None => Box::new(StdIo::new()),
+    };
+    #[cfg(feature = "bigint")]
+    let result = if args.bigint || args.arithmetic() == ArithmeticMode::Promote {
+        run::<num_bigint::BigInt>(grid, io, snapshot.as_ref(), &args)
+    } else {
+        run::<i64>(grid, io, snapshot.as_ref(), &args)
+    };
+    #[cfg(not(feature = "bigint"))]
+    let result = if args.arithmetic() == ArithmeticMode::Promote {
+        Err(PietError::runtime(
+            "promote arithmetic needs a build with the bigint feature",
+        ))
     } else {
-        program.execute(None, max_steps);
+        run::<i64>(grid, io, snapshot.as_ref(), &args)
+    };
+    if let Err(e) = result {
+        exit_with(e);
     }
 
     let elapsed = start_time.elapsed();
     debug!("Execution completed in: {:?}", elapsed);
 }
 
-pub fn load_image(path: &str, codel_size: u32) -> Vec<Vec<PietColor>> {
-    let start_time = std::time::Instant::now();
-    let img = image::open(path).expect("Failed to open image");
-    let img = img.to_rgb8();
-    let (width, height) = img.dimensions();
-    let mut result = vec![
-        vec![PietColor::default(); width as usize / codel_size as usize];
-        height as usize / codel_size as usize
-    ];
-    for y in (0..height).step_by(codel_size as usize) {
-        for x in (0..width).step_by(codel_size as usize) {
-            let mut codel = vec![PietColor::default(); codel_size as usize];
-            for i in 0..codel_size {
-                let pixel = img.get_pixel(x, y + i);
-                let rgb = [pixel[0], pixel[1], pixel[2]];
-                match PietColor::from_rgb(&rgb) {
-                    Ok(color) => codel[i as usize] = color,
-                    Err(_) => {
-                        error!("Invalid color detected at ({:?}, {:?}): {:?}", x, y, rgb);
-                        std::process::exit(1);
-                    }
-                }
+// Opens the program's input file. When resuming, the input the snapshotted run already
+// read is skipped.
+fn open_input(path: &str, snapshot: Option<&Snapshot>) -> io::Result<BufReader<File>> {
+    let mut input = BufReader::new(File::open(path)?);
+    if let Some(snapshot) = snapshot {
+        let skip = snapshot.input_read as u64;
+        io::copy(&mut input.by_ref().take(skip), &mut io::sink())?;
+    }
+    Ok(input)
+}
+
+fn run<V: PietValue>(
+    grid: Grid,
+    io: Box<dyn PietIo>,
+    snapshot: Option<&Snapshot>,
+    args: &Args,
+) -> piet_tool::Result<()> {
+    let mut program = PietProgram::<_, V>::with_io(grid, io);
+    program.set_arithmetic_mode(args.arithmetic());
+    program.set_loop_detection(args.detect_loops);
+    if let Some(snapshot) = snapshot {
+        program.restore(snapshot)?;
+    }
+    for breakpoint in &args.breakpoints {
+        program.add_breakpoint(*breakpoint)?;
+    }
+    if let Some(path) = &args.trace_file {
+        program.set_trace(Some(TraceWriter::create(path)?))?;
+    }
+    program.set_profiling(args.heatmap.is_some());
+    if let StopReason::Breakpoint(number) = program.execute(args.max_steps)? {
+        let (_, breakpoint) = program
+            .breakpoints()
+            .iter()
+            .find(|(n, _)| *n == number)
+            .expect("a breakpoint that was hit is set");
+        let mut stderr = io::stderr();
+        writeln!(stderr, "Breakpoint {} hit: {}", number, breakpoint)?;
+        debugger::write_state(&program, &mut stderr)?;
+    }
+    if let Some(path) = &args.heatmap {
+        render::draw_heatmap(&program, args.heatmap_scale).save(path)?;
+    }
+    if let Some(path) = &args.save_state {
+        program.snapshot().save(path)?;
+    }
+    Ok(())
+}
+
+fn debug(args: &DebugArgs) -> piet_tool::Result<()> {
+    let grid = load_image(&args.input_file, args.codel_size as u32)?;
+    let io: Box<dyn PietIo> = match &args.input {
+        Some(path) => Box::new(StreamIo::new(open_input(path, None)?, io::stdout())),
+        None => Box::new(StdIo::new()),
+    };
+    let mut program = PietProgram::<_, i64>::with_io(grid, io);
+    program.set_arithmetic_mode(args.arithmetic);
+    for breakpoint in &args.breakpoints {
+        program.add_breakpoint(*breakpoint)?;
+    }
+    Debugger::new(program).repl(StdinLines::default(), io::stdout())
+}
+
+// The TUI shows the program's output in a pane, so its input is read up front and its
+// output is kept in memory.
+#[cfg(feature = "tui")]
+fn tui(args: &DebugArgs) -> piet_tool::Result<()> {
+    let grid = load_image(&args.input_file, args.codel_size as u32)?;
+    let input = match &args.input {
+        Some(path) => std::fs::read(path)?,
+        None => Vec::new(),
+    };
+    let mut program = PietProgram::<_, i64>::with_io(grid, piet_tool::BufferIo::new(input));
+    program.set_arithmetic_mode(args.arithmetic);
+    for breakpoint in &args.breakpoints {
+        program.add_breakpoint(*breakpoint)?;
+    }
+    piet_tool::tui::run(program)
+}
+
+// Compares two traces, or the traces of two images run on the same input, and prints
+// where they first differ. Returns whether they are identical.
+fn trace_diff(args: &TraceDiffArgs) -> piet_tool::Result<bool> {
+    let mut stdout = io::stdout();
+    let comparison = if args.images {
+        let input = match &args.input {
+            Some(path) => std::fs::read(path)?,
+            None => {
+                let mut input = Vec::new();
+                io::stdin().read_to_end(&mut input)?;
+                input
+            }
+        };
+        let mut traces = Vec::new();
+        for image in [&args.a, &args.b] {
+            let grid = load_image(image, args.codel_size as u32)?;
+            let program =
+                PietProgram::<_, i64>::with_io(grid, piet_tool::BufferIo::new(input.clone()));
+            traces.push(TraceSteps::new(program, args.max_steps));
+        }
+        let (a, b) = traces.split_at_mut(1);
+        let comparison =
+            trace_diff::compare(a[0].by_ref().map(Ok), b[0].by_ref().map(Ok), args.context)?;
+        for (image, trace) in [&args.a, &args.b].into_iter().zip(&traces) {
+            if let Some(e) = trace.error() {
+                writeln!(stdout, "{} stopped with an error: {}", image, e)?;
             }
-            result[y as usize / codel_size as usize][x as usize / codel_size as usize] = codel[0];
+        }
+        comparison
+    } else {
+        let a = TraceReader::open(&args.a)?;
+        let b = TraceReader::open(&args.b)?;
+        if a.header().grid_hash != b.header().grid_hash {
+            writeln!(stdout, "The traces are of different images.")?;
+        }
+        trace_diff::compare(a, b, args.context)?
+    };
+    match comparison {
+        Comparison::Identical(steps) => {
+            writeln!(stdout, "The traces are identical ({} steps).", steps)?;
+            Ok(true)
+        }
+        Comparison::Diverged(divergence) => {
+            trace_diff::write_divergence(&divergence, &args.a, &args.b, &mut stdout)?;
+            Ok(false)
         }
     }
-    let elapsed = start_time.elapsed();
-    debug!(
-        "Loaded image with dimensions: {}x{} in {:?}",
-        width, height, elapsed
-    );
-    debug!(
-        "Size of grid: {}x{}. Codel size: {}",
-        result[0].len(),
-        result.len(),
-        codel_size
-    );
-    result
+}
+
+fn render_trace(args: &RenderArgs) -> piet_tool::Result<()> {
+    let grid = load_image(&args.input_file, args.codel_size as u32)?;
+    let input = match &args.input {
+        Some(path) => std::fs::read(path)?,
+        None => Vec::new(),
+    };
+    let mut program = PietProgram::<_, i64>::with_io(grid, piet_tool::BufferIo::new(input));
+    let options = GifOptions {
+        scale: args.scale,
+        every: args.every,
+        from: args.from,
+        to: args.to,
+        max_steps: args.max_steps,
+        delay: args.delay,
+        stack: args.stack,
+    };
+    let output = io::BufWriter::new(File::create(&args.output_file)?);
+    render::render_gif(&mut program, &options, output)?;
+    Ok(())
+}
+
+// Reads debugger commands from stdin a byte at a time, without holding on to the stdin
+// lock or buffering ahead, so that input meant for the program stays where it is.
+#[derive(Default)]
+struct StdinLines {
+    buffer: [u8; 1],
+    filled: bool,
+}
+
+impl Read for StdinLines {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for StdinLines {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if !self.filled {
+            if io::stdin().read(&mut self.buffer)? == 0 {
+                return Ok(&[]);
+            }
+            self.filled = true;
+        }
+        Ok(&self.buffer)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        if amount > 0 {
+            self.filled = false;
+        }
+    }
+}
+
+fn exit_with(e: PietError) -> ! {
+    error!("{}", e);
+    std::process::exit(e.exit_code());
 }
@@ -0,0 +1,55 @@
+//! Counting where a program spends its time: how often each color block was entered and
+//! which moves between blocks were made.
+
+use std::collections::HashMap;
+
+use crate::graph::BlockId;
+
+/// How often each block was entered and each move between two blocks was made while a
+/// program ran. Turn it on with [`crate::PietProgram::set_profiling`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    // Indexed by block id.
+    entries: Vec<u64>,
+    transitions: HashMap<(BlockId, BlockId), u64>,
+}
+
+impl Profile {
+    pub(crate) fn new(blocks: usize) -> Self {
+        Profile {
+            entries: vec![0; blocks],
+            transitions: HashMap::new(),
+        }
+    }
+
+    /// The number of times `block` was entered. The block the program was in when
+    /// profiling was turned on counts as entered once.
+    pub fn entries(&self, block: BlockId) -> u64 {
+        self.entries.get(block).copied().unwrap_or(0)
+    }
+
+    /// The largest number of times any block was entered.
+    pub fn max_entries(&self) -> u64 {
+        self.entries.iter().copied().max().unwrap_or(0)
+    }
+
+    /// Every move that was made, as `((from, to), count)`, most frequent first.
+    pub fn transitions(&self) -> Vec<((BlockId, BlockId), u64)> {
+        let mut transitions = self
+            .transitions
+            .iter()
+            .map(|(&blocks, &count)| (blocks, count))
+            .collect::<Vec<((BlockId, BlockId), u64)>>();
+        transitions.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        transitions
+    }
+
+    pub(crate) fn enter(&mut self, block: BlockId) {
+        self.entries[block] += 1;
+    }
+
+    pub(crate) fn record(&mut self, from: BlockId, to: BlockId) {
+        self.enter(to);
+        *self.transitions.entry((from, to)).or_insert(0) += 1;
+    }
+}
@@ -0,0 +1,461 @@
+//! Drawing programs as images: animations of a running program and heatmaps of where it
+//! spent its time.
+
+use std::io::Write;
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, Rgba, RgbaImage};
+
+use crate::{
+    color::contrast,
+    error::{PietError, Result},
+    interpreter::{Direction, PietProgram},
+    io::PietIo,
+    loader::Grid,
+    profile::Profile,
+    value::PietValue,
+};
+
+const PANEL_BACKGROUND: Rgba<u8> = Rgba([40, 40, 40, 255]);
+const PANEL_TEXT: Rgba<u8> = Rgba([255, 255, 255, 255]);
+// The stack panel's text is drawn with a 3x5 font, each font pixel this many pixels wide.
+const FONT_SCALE: u32 = 2;
+const GLYPH_WIDTH: u32 = 4 * FONT_SCALE;
+const LINE_HEIGHT: u32 = 7 * FONT_SCALE;
+const PANEL_MARGIN: u32 = 4;
+// The widest value the stack panel shows in full.
+const PANEL_CHARACTERS: usize = 12;
+// The panel always has room for at least this many values.
+const PANEL_LINES: u32 = 5;
+// How many of the most frequent moves a heatmap shows.
+const HEATMAP_TRANSITIONS: usize = 10;
+const UNVISITED: [u8; 3] = [64, 64, 64];
+const TRANSITION: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+/// How [`render_gif`] draws a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GifOptions {
+    /// The width and height of a codel in pixels.
+    pub scale: u32,
+    /// Draw a frame every this many steps.
+    pub every: u32,
+    /// The first step to draw.
+    pub from: u64,
+    /// The last step to draw, or -1 to draw until the program terminates.
+    pub to: i64,
+    /// With `to` at -1, how many steps the program gets to terminate in before rendering
+    /// fails, so that a program that never stops doesn't render forever.
+    pub max_steps: u64,
+    /// How long each frame is shown, in milliseconds.
+    pub delay: u32,
+    /// Show the stack in a panel to the right of the program.
+    pub stack: bool,
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        GifOptions {
+            scale: 10,
+            every: 1,
+            from: 0,
+            to: -1,
+            max_steps: 10_000,
+            delay: 100,
+            stack: false,
+        }
+    }
+}
+
+/// Runs `program` and writes an animated GIF of its run to `output`, one frame per state
+/// in the step range (see [`GifOptions`]). The state the program terminates in is always
+/// drawn, if it is in range. Returns the number of frames, or an error if the program
+/// runs for `max_steps` steps without terminating and no last step was given.
+pub fn render_gif<I: PietIo, V: PietValue>(
+    program: &mut PietProgram<I, V>,
+    options: &GifOptions,
+    output: impl Write,
+) -> Result<usize> {
+    let every = options.every.max(1) as u64;
+    // Where to stop drawing, if anywhere.
+    let to = u64::try_from(options.to).ok();
+    let mut encoder = GifEncoder::new(output);
+    encoder.set_repeat(Repeat::Infinite)?;
+    let mut frames = 0;
+    loop {
+        let step = program.steps();
+        let terminated = program.is_terminated();
+        let in_range = step >= options.from && to.is_none_or(|to| step <= to);
+        if in_range && ((step - options.from).is_multiple_of(every) || terminated) {
+            let image = draw_state(program, options.scale, options.stack);
+            let delay = Delay::from_numer_denom_ms(options.delay, 1);
+            encoder.encode_frame(Frame::from_parts(image, 0, 0, delay))?;
+            frames += 1;
+        }
+        if terminated || to.is_some_and(|to| step >= to) {
+            break;
+        }
+        if to.is_none() && step >= options.max_steps {
+            return Err(PietError::runtime(format!(
+                "the program did not terminate within {} steps",
+                options.max_steps
+            ))
+            .at(program.position()));
+        }
+        program.step_once()?;
+    }
+    info!("Rendered {} frames.", frames);
+    Ok(frames)
+}
+
+/// Draws the program with each codel `scale` pixels wide, the current block outlined and
+/// an arrow in the direction of the DP on the codel the block will be left from.
+/// With `stack`, the stack is listed (top first) in a panel to the right.
+pub fn draw_state<I: PietIo, V: PietValue>(
+    program: &PietProgram<I, V>,
+    scale: u32,
+    stack: bool,
+) -> RgbaImage {
+    let scale = scale.max(1);
+    let grid = program.grid();
+    let (width, height) = grid_size(grid, scale);
+    let panel_width = if stack {
+        PANEL_CHARACTERS as u32 * GLYPH_WIDTH + 2 * PANEL_MARGIN
+    } else {
+        0
+    };
+    let image_height = if stack {
+        height.max(PANEL_LINES * LINE_HEIGHT + 2 * PANEL_MARGIN)
+    } else {
+        height
+    };
+    let mut image = RgbaImage::from_pixel(width + panel_width, image_height, PANEL_BACKGROUND);
+    draw_grid(&mut image, grid, scale);
+
+    let graph = program.graph();
+    let current = program.current_block();
+    let [r, g, b] = contrast(current.color.to_rgb());
+    let outline = Rgba([r, g, b, 255]);
+    let in_block = |(x, y): (i32, i32)| {
+        x >= 0
+            && y >= 0
+            && (y as usize) < grid.len()
+            && (x as usize) < grid[0].len()
+            && graph.block_at((x, y)).id == current.id
+    };
+    let thickness = (scale / 8).max(1);
+    for &(x, y) in program.current_block_codels() {
+        for v in 0..scale {
+            for u in 0..scale {
+                let edge = (u < thickness && !in_block((x - 1, y)))
+                    || (u >= scale - thickness && !in_block((x + 1, y)))
+                    || (v < thickness && !in_block((x, y - 1)))
+                    || (v >= scale - thickness && !in_block((x, y + 1)));
+                if edge {
+                    image.put_pixel(x as u32 * scale + u, y as u32 * scale + v, outline);
+                }
+            }
+        }
+    }
+
+    let dp = program.direction_pointer();
+    let exit = graph
+        .block(current.id)
+        .exit(dp, program.codel_chooser())
+        .codel;
+    draw_arrow(&mut image, exit, dp, scale, outline);
+
+    if stack {
+        let values = program
+            .stack()
+            .values()
+            .iter()
+            .rev()
+            .map(|value| value.to_string())
+            .collect::<Vec<String>>();
+        draw_stack(&mut image, width, &values);
+    }
+    image
+}
+
+/// Draws the program with each codel `scale` pixels wide and tinted by how often its block
+/// was entered according to the program's [`Profile`], from blue (rarely) to red (most
+/// often), on a logarithmic scale. Blocks that were never entered are greyed out. Arrows
+/// between the centers of blocks show the most frequent moves, the thickest being the
+/// most frequent. Without profiling (see [`PietProgram::set_profiling`]) nothing is tinted.
+pub fn draw_heatmap<I: PietIo, V: PietValue>(program: &PietProgram<I, V>, scale: u32) -> RgbaImage {
+    let scale = scale.max(1);
+    let grid = program.grid();
+    let graph = program.graph();
+    let empty = Profile::default();
+    let profile = program.profile().unwrap_or(&empty);
+    let (width, height) = grid_size(grid, scale);
+    let mut image = RgbaImage::new(width, height);
+    let most = (profile.max_entries() as f32).ln_1p();
+    for block in graph.blocks() {
+        let entries = profile.entries(block.id);
+        let tint = if entries == 0 {
+            UNVISITED
+        } else if most == 0.0 {
+            heat(1.0)
+        } else {
+            heat((entries as f32).ln_1p() / most)
+        };
+        // Keep a little of the codel's own color so that the program stays recognizable.
+        let [r, g, b] = block.color.to_rgb();
+        let color = Rgba([
+            mix(r, tint[0], 0.75),
+            mix(g, tint[1], 0.75),
+            mix(b, tint[2], 0.75),
+            255,
+        ]);
+        for &(x, y) in &block.codels {
+            fill(
+                &mut image,
+                (x as u32 * scale, y as u32 * scale),
+                (scale, scale),
+                color,
+            );
+        }
+    }
+
+    let transitions = profile.transitions();
+    let busiest = transitions.first().map_or(1, |(_, count)| *count) as f32;
+    let center = |id: usize| {
+        let codels = &graph.block(id).codels;
+        let (sum_x, sum_y) = codels.iter().fold((0.0, 0.0), |(sx, sy), &(x, y)| {
+            (sx + x as f32, sy + y as f32)
+        });
+        let count = codels.len() as f32;
+        (
+            (sum_x / count + 0.5) * scale as f32,
+            (sum_y / count + 0.5) * scale as f32,
+        )
+    };
+    // Draw the least frequent first so that the most frequent end up on top.
+    for &((from, to), count) in transitions.iter().take(HEATMAP_TRANSITIONS).rev() {
+        if from == to {
+            continue;
+        }
+        let thickness = 1.0 + (scale as f32 / 3.0) * count as f32 / busiest;
+        draw_transition(&mut image, center(from), center(to), thickness);
+    }
+    image
+}
+
+// Draws an arrow from `start` to `end`. It keeps to its right, so that moves both ways
+// between two blocks don't hide each other.
+fn draw_transition(image: &mut RgbaImage, start: (f32, f32), end: (f32, f32), thickness: f32) {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    let (ux, uy) = (dx / length, dy / length);
+    // To the right of the direction of travel, with y pointing down.
+    let (rx, ry) = (-uy, ux);
+    let offset = thickness / 2.0 + 1.0;
+    let start = (start.0 + rx * offset, start.1 + ry * offset);
+    let end = (end.0 + rx * offset, end.1 + ry * offset);
+    let head = (thickness * 3.0).max(6.0).min(length / 2.0);
+    let base = (end.0 - ux * head, end.1 - uy * head);
+    draw_line(image, start, base, thickness);
+    let half = head / 2.0;
+    fill_triangle(
+        image,
+        [
+            end,
+            (base.0 + rx * half, base.1 + ry * half),
+            (base.0 - rx * half, base.1 - ry * half),
+        ],
+    );
+}
+
+fn draw_line(image: &mut RgbaImage, start: (f32, f32), end: (f32, f32), thickness: f32) {
+    let radius = thickness / 2.0;
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length_squared = (dx * dx + dy * dy).max(f32::EPSILON);
+    for_pixels_in(
+        image,
+        (start.0.min(end.0) - radius, start.1.min(end.1) - radius),
+        (start.0.max(end.0) + radius, start.1.max(end.1) + radius),
+        |(x, y)| {
+            // The distance from the pixel to the nearest point of the segment.
+            let t = (((x - start.0) * dx + (y - start.1) * dy) / length_squared).clamp(0.0, 1.0);
+            let (px, py) = (start.0 + t * dx - x, start.1 + t * dy - y);
+            (px * px + py * py).sqrt() <= radius
+        },
+    );
+}
+
+fn fill_triangle(image: &mut RgbaImage, [a, b, c]: [(f32, f32); 3]) {
+    // Which side of the line from `p` to `q` the point `r` is on.
+    let side = |p: (f32, f32), q: (f32, f32), r: (f32, f32)| {
+        (q.0 - p.0) * (r.1 - p.1) - (q.1 - p.1) * (r.0 - p.0)
+    };
+    for_pixels_in(
+        image,
+        (a.0.min(b.0).min(c.0), a.1.min(b.1).min(c.1)),
+        (a.0.max(b.0).max(c.0), a.1.max(b.1).max(c.1)),
+        |point| {
+            let sides = [side(a, b, point), side(b, c, point), side(c, a, point)];
+            sides.iter().all(|&s| s >= 0.0) || sides.iter().all(|&s| s <= 0.0)
+        },
+    );
+}
+
+// Colors the pixels between the corners `low` and `high` whose centers pass `inside`.
+fn for_pixels_in(
+    image: &mut RgbaImage,
+    low: (f32, f32),
+    high: (f32, f32),
+    inside: impl Fn((f32, f32)) -> bool,
+) {
+    let (width, height) = (image.width() as f32, image.height() as f32);
+    let (left, top) = (low.0.max(0.0) as u32, low.1.max(0.0) as u32);
+    let (right, bottom) = (high.0.min(width - 1.0), high.1.min(height - 1.0));
+    if right < 0.0 || bottom < 0.0 {
+        return;
+    }
+    for y in top..=bottom as u32 {
+        for x in left..=right as u32 {
+            if inside((x as f32 + 0.5, y as f32 + 0.5)) {
+                image.put_pixel(x, y, TRANSITION);
+            }
+        }
+    }
+}
+
+// A color from blue through cyan, green and yellow to red as `t` goes from 0 to 1.
+fn heat(t: f32) -> [u8; 3] {
+    let t = t.clamp(0.0, 1.0) * 4.0;
+    let ramp = |t: f32| (t.clamp(0.0, 1.0) * 255.0).round() as u8;
+    match t {
+        t if t < 1.0 => [0, ramp(t), 255],
+        t if t < 2.0 => [0, 255, ramp(2.0 - t)],
+        t if t < 3.0 => [ramp(t - 2.0), 255, 0],
+        t => [255, ramp(4.0 - t), 0],
+    }
+}
+
+// `a` with `amount` of `b` mixed in.
+fn mix(a: u8, b: u8, amount: f32) -> u8 {
+    (a as f32 * (1.0 - amount) + b as f32 * amount).round() as u8
+}
+
+// The size in pixels of a grid drawn with each codel `scale` pixels wide.
+fn grid_size(grid: &Grid, scale: u32) -> (u32, u32) {
+    let width = grid.first().map_or(0, |row| row.len()) as u32;
+    (width * scale, grid.len() as u32 * scale)
+}
+
+fn draw_grid(image: &mut RgbaImage, grid: &Grid, scale: u32) {
+    for (y, row) in grid.iter().enumerate() {
+        for (x, color) in row.iter().enumerate() {
+            let [r, g, b] = color.to_rgb();
+            fill(
+                image,
+                (x as u32 * scale, y as u32 * scale),
+                (scale, scale),
+                Rgba([r, g, b, 255]),
+            );
+        }
+    }
+}
+
+fn fill(
+    image: &mut RgbaImage,
+    (left, top): (u32, u32),
+    (width, height): (u32, u32),
+    color: Rgba<u8>,
+) {
+    for y in top..top + height {
+        for x in left..left + width {
+            image.put_pixel(x, y, color);
+        }
+    }
+}
+
+// Draws a triangle pointing in `direction` inside `codel`.
+fn draw_arrow(
+    image: &mut RgbaImage,
+    codel: (i32, i32),
+    direction: Direction,
+    scale: u32,
+    color: Rgba<u8>,
+) {
+    let (left, top) = (codel.0 as u32 * scale, codel.1 as u32 * scale);
+    // Too small for a triangle: mark the whole codel.
+    if scale < 5 {
+        fill(image, (left, top), (scale, scale), color);
+        return;
+    }
+    let last = (scale - 1) as f32;
+    let margin = (scale / 5) as f32;
+    let half = last / 2.0 - margin;
+    for v in 0..scale {
+        for u in 0..scale {
+            // Turn the pixel so that the triangle can be tested as if it pointed right.
+            let (along, across) = match direction {
+                Direction::Right => (u as f32, v as f32),
+                Direction::Down => (v as f32, u as f32),
+                Direction::Left => (last - u as f32, v as f32),
+                Direction::Up => (last - v as f32, u as f32),
+            };
+            if along < margin || along > last - margin {
+                continue;
+            }
+            let width = half * (last - margin - along) / (last - 2.0 * margin);
+            if (across - last / 2.0).abs() <= width {
+                image.put_pixel(left + u, top + v, color);
+            }
+        }
+    }
+}
+
+// Lists `values` top to bottom in the panel that starts at pixel column `left`.
+fn draw_stack(image: &mut RgbaImage, left: u32, values: &[String]) {
+    let lines = ((image.height() - 2 * PANEL_MARGIN) / LINE_HEIGHT) as usize;
+    for (line, value) in values.iter().enumerate().take(lines) {
+        let text = if line + 1 == lines && values.len() > lines {
+            "...".to_string()
+        } else if value.len() > PANEL_CHARACTERS {
+            format!("{}..", &value[..PANEL_CHARACTERS - 2])
+        } else {
+            value.clone()
+        };
+        let top = PANEL_MARGIN + line as u32 * LINE_HEIGHT;
+        draw_text(image, (left + PANEL_MARGIN, top), &text);
+    }
+}
+
+fn draw_text(image: &mut RgbaImage, (left, top): (u32, u32), text: &str) {
+    for (index, c) in text.chars().enumerate() {
+        let rows = glyph(c);
+        let x = left + index as u32 * GLYPH_WIDTH;
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) != 0 {
+                    let position = (x + column * FONT_SCALE, top + row as u32 * FONT_SCALE);
+                    fill(image, position, (FONT_SCALE, FONT_SCALE), PANEL_TEXT);
+                }
+            }
+        }
+    }
+}
+
+// The rows of a character in a 3x5 font, top first, one bit per pixel. Stack values only
+// need digits, the minus sign and dots.
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        _ => [0; 5],
+    }
+}
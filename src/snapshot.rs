@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{PietError, Result},
+    interpreter::{CodelChooser, Direction},
+    loader::Grid,
+};
+
+/// The version of the snapshot format written by this build.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// The complete state of a running program, for checkpointing long computations.
+///
+/// Snapshots are stored as JSON. Stack values are written as decimal strings, so a
+/// snapshot can be resumed with any value type that can hold them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    /// Hash of the program's grid (see [`grid_hash`]), to make sure the snapshot is resumed
+    /// against the image it was taken from.
+    pub grid_hash: String,
+    /// The stack, bottom first.
+    pub stack: Vec<String>,
+    pub direction_pointer: Direction,
+    pub codel_chooser: CodelChooser,
+    pub position: (i32, i32),
+    pub steps: u64,
+    pub terminated: bool,
+    /// Input bytes that were read ahead but not consumed yet, next byte last.
+    pub pending_input: Vec<u8>,
+    /// The total number of bytes read from the program's input.
+    pub input_read: usize,
+}
+
+impl Snapshot {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a snapshot can always be serialized")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let snapshot: Snapshot = serde_json::from_str(json)
+            .map_err(|e| PietError::Snapshot(format!("invalid snapshot: {}", e)))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(PietError::Snapshot(format!(
+                "unsupported snapshot version {} (expected {})",
+                snapshot.version, SNAPSHOT_VERSION
+            )));
+        }
+        Ok(snapshot)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_json())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+}
+
+/// A stable 64-bit FNV-1a hash of a grid's dimensions and colors, as a hex string.
+pub fn grid_hash(grid: &Grid) -> String {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    let mut hash = OFFSET_BASIS;
+    let mut feed = |byte: u8| {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(PRIME);
+    };
+    let height = grid.len() as u32;
+    let width = grid.first().map_or(0, |row| row.len()) as u32;
+    width.to_le_bytes().into_iter().for_each(&mut feed);
+    height.to_le_bytes().into_iter().for_each(&mut feed);
+    for color in grid.iter().flatten() {
+        feed(color.name as u8);
+    }
+    format!("{:016x}", hash)
+}
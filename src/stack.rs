@@ -1,11 +1,13 @@
-use std::io::{self, Read};
+use std::fmt;
 
-#[derive(Debug)]
-pub struct Stack {
-    data: Vec<i32>,
+use crate::value::PietValue;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stack<V = i64> {
+    data: Vec<V>,
 }
 
-impl Stack {
+impl<V: PietValue> Stack<V> {
     pub fn new() -> Self {
         Stack { data: Vec::new() }
     }
@@ -14,11 +16,11 @@ impl Stack {
         self.data.is_empty()
     }
 
-    pub fn push(&mut self, value: i32) {
+    pub fn push(&mut self, value: V) {
         self.data.push(value);
     }
 
-    pub fn pop(&mut self) -> Option<i32> {
+    pub fn pop(&mut self) -> Option<V> {
         if self.is_empty() {
             debug!("Attempted to pop from empty stack. Ignoring.");
             return None;
@@ -49,44 +51,32 @@ impl Stack {
         };
 
         // Check if the depth is valid
-        if depth < 0 {
+        if depth < V::zero() {
             error!("Invalid depth {} for roll operation", depth);
             return;
-        } else if depth == 0 {
+        } else if depth.is_zero() {
             return; // No action needed if depth is 0
         }
 
-        let depth = depth as usize;
-        if depth > self.len() {
-            error!("Depth {} exceeds stack size", depth);
-            return;
-        }
+        let depth = match depth.to_i64() {
+            Some(depth) if depth as u64 <= self.len() as u64 => depth as usize,
+            _ => {
+                error!("Depth {} exceeds stack size", depth);
+                return;
+            }
+        };
 
-        // Calculate the effective number of rolls
-        let rolls = rolls % depth as i32;
-        let rolls = if rolls < 0 {
-            rolls + depth as i32 // Adjust for negative rolls
-        } else {
-            rolls
-        } as usize;
+        // Calculate the effective number of rolls, adjusting for negative rolls
+        let rolls = rolls.rem_euclid_u64(depth as u64) as usize;
 
         // Only perform the roll if the number of rolls is non-zero
         if rolls == 0 {
             return;
         }
 
-        // Extract the slice to be rolled
+        // Roll the top `depth` values in place
         let len = self.len();
-        let mut stack_slice = self.data[len - depth..].to_vec();
-
-        // Perform the roll
-        for _ in 0..rolls {
-            let value = stack_slice.pop().unwrap(); // Remove the last element
-            stack_slice.insert(0, value); // Insert it at the beginning
-        }
-
-        // Replace the original slice with the rolled slice
-        self.data[len - depth..].copy_from_slice(&stack_slice);
+        self.data[len - depth..].rotate_right(rolls);
         trace!("Rolled stack: depth {} rolls {}", depth, rolls);
     }
 
@@ -94,60 +84,31 @@ impl Stack {
         self.data.len()
     }
 
-    pub fn in_char(&mut self) {
-        let mut buffer = [0; 1];
-        match io::stdin().read_exact(&mut buffer) {
-            Ok(_) => {
-                let ch = buffer[0] as i32;
-                self.push(ch);
-                trace!(
-                    "Input character: {} (ASCII value: {})",
-                    ch as u8 as char,
-                    ch
-                );
-            }
-            Err(e) => {
-                error!("Failed to read character input: {}", e);
-            }
-        }
+    /// The stack contents, bottom first.
+    pub fn values(&self) -> &[V] {
+        &self.data
     }
 
-    pub fn in_number(&mut self) {
-        // Read the entire input line
-        let mut input = String::new();
-        match io::stdin().read_line(&mut input) {
-            Ok(_) => {
-                // Trim the input to remove any surrounding whitespace or newlines
-                let input = input.trim();
-
-                // Attempt to parse the input as an integer
-                match input.parse::<i32>() {
-                    Ok(number) => {
-                        self.push(number);
-                        trace!("Input number: {}", number);
-                    }
-                    Err(e) => {
-                        error!("Failed to parse input as number: {}", e);
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Failed to read number input: {}", e);
-            }
-        }
+    /// The value on top of the stack, if any.
+    pub fn peek(&self) -> Option<&V> {
+        self.data.last()
     }
+}
 
-    pub fn to_string(&self) -> String {
-        self.data
+// Prints the stack top first, separated by spaces.
+impl<V: PietValue> fmt::Display for Stack<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values = self
+            .data
             .iter()
-            .map(|x| x.to_string())
             .rev()
-            .collect::<Vec<String>>()
-            .join(" ")
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>();
+        write!(f, "{}", values.join(" "))
     }
 }
 
-impl Default for Stack {
+impl<V: PietValue> Default for Stack<V> {
     fn default() -> Self {
         Self::new()
     }
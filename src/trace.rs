@@ -0,0 +1,257 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    color::ColorName,
+    command::Command,
+    error::{PietError, Result},
+    interpreter::{CodelChooser, Direction, PietProgram},
+    io::PietIo,
+    value::PietValue,
+};
+
+/// The version of the trace format written by this build.
+pub const TRACE_VERSION: u32 = 1;
+
+/// How many values from the top of the stack each step records.
+pub const TOP_VALUES: usize = 5;
+
+/// The first line of a trace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceHeader {
+    pub version: u32,
+    /// Hash of the program's grid (see [`crate::snapshot::grid_hash`]).
+    pub grid_hash: String,
+}
+
+/// A color block, identified by its topmost, leftmost codel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceBlock {
+    pub x: i32,
+    pub y: i32,
+    pub color: ColorName,
+}
+
+/// One line of a trace: what a single step did.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceStep {
+    /// The step number, counting from 0.
+    pub step: u64,
+    /// The block the interpreter started in.
+    pub from: TraceBlock,
+    /// The block the interpreter ended up in. `None` if the program terminated.
+    pub to: Option<TraceBlock>,
+    /// The DP after the step.
+    #[serde(rename = "dp")]
+    pub direction_pointer: Direction,
+    /// The CC after the step.
+    #[serde(rename = "cc")]
+    pub codel_chooser: CodelChooser,
+    /// The command that was executed, if any.
+    pub command: Option<Command>,
+    /// The number of values on the stack after the step.
+    pub depth: usize,
+    /// Up to [`TOP_VALUES`] values from the top of the stack, top first, as decimal strings.
+    pub top: Vec<String>,
+    /// The value an input command pushed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+    /// The text an output command wrote.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Whether an arithmetic command overflowed (see [`crate::ArithmeticMode`]).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub overflowed: bool,
+}
+
+/// Writes a trace of a running program as JSON lines: a [`TraceHeader`], then one
+/// [`TraceStep`] per step. Install it with [`PietProgram::set_trace`].
+pub struct TraceWriter {
+    sink: Sink,
+    // The output written during the step being executed.
+    pub(crate) output: Vec<u8>,
+}
+
+enum Sink {
+    Writer(Box<dyn Write + Send>),
+    // Steps that have been traced but not taken by `TraceSteps` yet.
+    Queue(VecDeque<TraceStep>),
+}
+
+impl TraceWriter {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        TraceWriter {
+            sink: Sink::Writer(Box::new(writer)),
+            output: Vec::new(),
+        }
+    }
+
+    /// Creates (or truncates) the file at `path` and writes the trace to it.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+
+    fn queue() -> Self {
+        TraceWriter {
+            sink: Sink::Queue(VecDeque::new()),
+            output: Vec::new(),
+        }
+    }
+
+    pub(crate) fn write_header(&mut self, header: &TraceHeader) -> Result<()> {
+        match &mut self.sink {
+            Sink::Writer(writer) => write_line(writer, header),
+            Sink::Queue(_) => Ok(()),
+        }
+    }
+
+    pub(crate) fn write_step(&mut self, step: TraceStep) -> Result<()> {
+        match &mut self.sink {
+            Sink::Writer(writer) => write_line(writer, &step),
+            Sink::Queue(queue) => {
+                queue.push_back(step);
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) fn flush(&mut self) -> Result<()> {
+        if let Sink::Writer(writer) = &mut self.sink {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn next_step(&mut self) -> Option<TraceStep> {
+        match &mut self.sink {
+            Sink::Writer(_) => None,
+            Sink::Queue(queue) => queue.pop_front(),
+        }
+    }
+}
+
+impl fmt::Debug for TraceWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TraceWriter").finish_non_exhaustive()
+    }
+}
+
+fn write_line(writer: &mut impl Write, value: &impl Serialize) -> Result<()> {
+    serde_json::to_writer(&mut *writer, value).map_err(std::io::Error::from)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Reads a trace written by a [`TraceWriter`], one step at a time.
+#[derive(Debug)]
+pub struct TraceReader<R> {
+    header: TraceHeader,
+    lines: Lines<R>,
+    // The number of the line that is read next, counting from 1.
+    line: usize,
+}
+
+impl<R: BufRead> TraceReader<R> {
+    /// Reads the header of the trace and checks that this build understands its version.
+    pub fn new(reader: R) -> Result<Self> {
+        let mut lines = reader.lines();
+        let header: TraceHeader = match lines.next() {
+            Some(line) => parse_line(&line?, 1)?,
+            None => return Err(PietError::Trace("the trace is empty".to_string())),
+        };
+        if header.version != TRACE_VERSION {
+            return Err(PietError::Trace(format!(
+                "unsupported trace version {} (expected {})",
+                header.version, TRACE_VERSION
+            )));
+        }
+        Ok(TraceReader {
+            header,
+            lines,
+            line: 2,
+        })
+    }
+
+    pub fn header(&self) -> &TraceHeader {
+        &self.header
+    }
+}
+
+impl TraceReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> Iterator for TraceReader<R> {
+    type Item = Result<TraceStep>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = match self.lines.next()? {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e.into())),
+        };
+        let number = self.line;
+        self.line += 1;
+        Some(parse_line(&line, number))
+    }
+}
+
+fn parse_line<T: serde::de::DeserializeOwned>(line: &str, number: usize) -> Result<T> {
+    serde_json::from_str(line)
+        .map_err(|e| PietError::Trace(format!("invalid trace line {}: {}", number, e)))
+}
+
+/// Runs a program and yields the trace of each step as it is executed, without writing
+/// it anywhere. Stops when the program terminates, fails or has executed `max_steps`
+/// steps (-1 for no limit); a failure can be looked at with [`TraceSteps::error`].
+#[derive(Debug)]
+pub struct TraceSteps<I: PietIo, V: PietValue = i64> {
+    program: PietProgram<I, V>,
+    max_steps: i64,
+    error: Option<PietError>,
+}
+
+impl<I: PietIo, V: PietValue> TraceSteps<I, V> {
+    pub fn new(mut program: PietProgram<I, V>, max_steps: i64) -> Self {
+        program
+            .set_trace(Some(TraceWriter::queue()))
+            .expect("tracing to memory can't fail");
+        TraceSteps {
+            program,
+            max_steps,
+            error: None,
+        }
+    }
+
+    pub fn program(&self) -> &PietProgram<I, V> {
+        &self.program
+    }
+
+    /// The error the program stopped with, if it failed.
+    pub fn error(&self) -> Option<&PietError> {
+        self.error.as_ref()
+    }
+}
+
+impl<I: PietIo, V: PietValue> Iterator for TraceSteps<I, V> {
+    type Item = TraceStep;
+
+    fn next(&mut self) -> Option<TraceStep> {
+        let program = &mut self.program;
+        let limit_reached = u64::try_from(self.max_steps).is_ok_and(|max| program.steps() >= max);
+        if program.is_terminated() || limit_reached || self.error.is_some() {
+            return None;
+        }
+        if let Err(e) = program.step_once() {
+            self.error = Some(e);
+            return None;
+        }
+        program.trace_mut().and_then(TraceWriter::next_step)
+    }
+}
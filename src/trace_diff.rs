@@ -0,0 +1,195 @@
+//! Finding where two execution traces first differ, e.g. after changing an image or
+//! between two versions of the interpreter.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+
+use crate::{
+    error::Result,
+    trace::{TraceBlock, TraceStep},
+};
+
+/// Something two aligned steps disagree on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difference {
+    /// The blocks the steps went from or to.
+    Block,
+    /// The DP or CC after the steps.
+    Pointer,
+    /// The commands the steps executed.
+    Command,
+    /// The depth or the top values of the stack after the steps.
+    Stack,
+    /// One of the traces has already ended.
+    End,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Difference::Block => "block",
+            Difference::Pointer => "DP/CC",
+            Difference::Command => "command",
+            Difference::Stack => "stack",
+            Difference::End => "end of trace",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Where two traces first differ, with some context around it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The position of the first differing step in both traces, counting from 0.
+    pub index: usize,
+    pub differences: Vec<Difference>,
+    /// The steps just before the divergence, which both traces agree on.
+    pub before: Vec<TraceStep>,
+    /// The diverging step and the steps after it, from each trace. Shorter than the
+    /// others if that trace ends.
+    pub a: Vec<TraceStep>,
+    pub b: Vec<TraceStep>,
+}
+
+/// The result of comparing two traces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    /// The traces agree on all of their steps; holds the number of steps.
+    Identical(usize),
+    Diverged(Divergence),
+}
+
+/// Compares two traces step by step and returns the first step where they differ, with
+/// up to `context` steps before and after it. Neither trace is read past that point, so
+/// this works on traces of programs that never stop, as long as they diverge.
+pub fn compare(
+    mut a: impl Iterator<Item = Result<TraceStep>>,
+    mut b: impl Iterator<Item = Result<TraceStep>>,
+    context: usize,
+) -> Result<Comparison> {
+    let mut before = VecDeque::with_capacity(context);
+    let mut index = 0;
+    loop {
+        let (step_a, step_b) = match (a.next().transpose()?, b.next().transpose()?) {
+            (None, None) => return Ok(Comparison::Identical(index)),
+            steps => steps,
+        };
+        let differences = differences(step_a.as_ref(), step_b.as_ref());
+        if differences.is_empty() {
+            if context > 0 {
+                if before.len() == context {
+                    before.pop_front();
+                }
+                before.extend(step_a);
+            }
+            index += 1;
+            continue;
+        }
+        let mut after_a = step_a.into_iter().collect::<Vec<TraceStep>>();
+        after_a.extend(a.take(context).collect::<Result<Vec<TraceStep>>>()?);
+        let mut after_b = step_b.into_iter().collect::<Vec<TraceStep>>();
+        after_b.extend(b.take(context).collect::<Result<Vec<TraceStep>>>()?);
+        return Ok(Comparison::Diverged(Divergence {
+            index,
+            differences,
+            before: before.into(),
+            a: after_a,
+            b: after_b,
+        }));
+    }
+}
+
+fn differences(a: Option<&TraceStep>, b: Option<&TraceStep>) -> Vec<Difference> {
+    let (a, b) = match (a, b) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return vec![Difference::End],
+    };
+    let mut differences = Vec::new();
+    if a.from != b.from || a.to != b.to {
+        differences.push(Difference::Block);
+    }
+    if (a.direction_pointer, a.codel_chooser) != (b.direction_pointer, b.codel_chooser) {
+        differences.push(Difference::Pointer);
+    }
+    if a.command != b.command {
+        differences.push(Difference::Command);
+    }
+    if (a.depth, &a.top) != (b.depth, &b.top) {
+        differences.push(Difference::Stack);
+    }
+    differences
+}
+
+/// Writes a divergence for people to read, naming the traces `name_a` and `name_b`. Steps
+/// both traces agree on are shown once; the others are shown from each trace.
+pub fn write_divergence(
+    divergence: &Divergence,
+    name_a: &str,
+    name_b: &str,
+    output: &mut impl Write,
+) -> Result<()> {
+    let differences = divergence
+        .differences
+        .iter()
+        .map(|difference| difference.to_string())
+        .collect::<Vec<String>>();
+    writeln!(
+        output,
+        "The traces first differ at step {} ({}).",
+        divergence.index,
+        differences.join(", ")
+    )?;
+    let first = divergence.index - divergence.before.len();
+    for (offset, step) in divergence.before.iter().enumerate() {
+        writeln!(output, "  {:>6}  {}", first + offset, describe(step))?;
+    }
+    let width = name_a.len().max(name_b.len());
+    let after = divergence.a.len().max(divergence.b.len());
+    for offset in 0..after {
+        let marker = if offset == 0 { '>' } else { ' ' };
+        let index = divergence.index + offset;
+        for (name, steps) in [(name_a, &divergence.a), (name_b, &divergence.b)] {
+            let step = steps
+                .get(offset)
+                .map_or("(trace ended)".to_string(), describe);
+            writeln!(
+                output,
+                "{} {:>6}  {:<width$}  {}",
+                marker,
+                index,
+                name,
+                step,
+                width = width
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// One line describing a step: where it went, what it did and what it left on the stack.
+fn describe(step: &TraceStep) -> String {
+    let block = |block: &TraceBlock| format!("({}, {}) {:?}", block.x, block.y, block.color);
+    let to = step.to.as_ref().map_or("terminated".to_string(), block);
+    let command = step
+        .command
+        .map_or("-".to_string(), |command| format!("{:?}", command));
+    let mut stack = step
+        .top
+        .iter()
+        .map(|value| format!(" {}", value))
+        .collect::<String>();
+    if step.depth > step.top.len() {
+        stack.push_str(" ..");
+    }
+    format!(
+        "{} -> {}, DP {:?}, CC {:?}, {}, stack ({}):{}",
+        block(&step.from),
+        to,
+        step.direction_pointer,
+        step.codel_chooser,
+        command,
+        step.depth,
+        stack
+    )
+}
@@ -1,13 +1,101 @@
+use crate::cfg::{ControlFlowGraph, Node};
 use crate::command::Command;
-use crate::interpreter::PietProgram;
+use crate::error::{PietError, Result};
+use crate::graph::BlockGraph;
+use crate::interpreter::{CodelChooser, Direction};
+use crate::loader::Grid;
+use std::collections::HashSet;
+use std::fmt::{self, Display};
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
+use std::str::FromStr;
+
+pub mod c;
+pub mod rust;
+pub mod wat;
+
+// The runtime every translation starts with: one word per command, and the DP and CC.
+const PRELUDE: &str = include_str!("base.f");
+
+// The standard Forth words the generated code may use besides the prelude's own.
+const STANDARD_WORDS: [&str; 18] = [
+    ":", ";", "VARIABLE", "@", "!", "=", "0<", "0=", "IF", "ELSE", "THEN", "CASE", "OF", "ENDOF",
+    "ENDCASE", "BEGIN", "WHILE", "REPEAT",
+];
+
+/// The language a program is translated to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+    /// Forth, with the prelude in `base.f` (see [`Translator`]).
+    #[default]
+    Forth,
+    /// A single C99 file (see [`c::translate`]).
+    C,
+    /// A Rust source file that depends on this crate (see [`rust::translate`]).
+    Rust,
+    /// A WebAssembly module in the text format (see [`wat::translate`]).
+    Wat,
+}
+
+impl Target {
+    /// The file the translation is written to if no other is given.
+    pub fn default_output(self) -> &'static str {
+        match self {
+            Target::Forth => "out.f",
+            Target::C => "out.c",
+            Target::Rust => "out.rs",
+            Target::Wat => "out.wat",
+        }
+    }
+
+    /// Translates the program and writes the code to `output_file`.
+    pub fn translate(self, grid: &Grid, output_file: &str) -> Result<()> {
+        match self {
+            Target::Forth => {
+                let mut translator = Translator::new(output_file.to_string());
+                translator.translate(grid)?;
+                translator.flush()
+            }
+            Target::C => save(output_file, &c::translate(grid)?),
+            Target::Rust => save(output_file, &rust::translate(grid)?),
+            Target::Wat => save(output_file, &wat::translate(grid)?),
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "forth" => Ok(Target::Forth),
+            "c" => Ok(Target::C),
+            "rust" => Ok(Target::Rust),
+            "wat" => Ok(Target::Wat),
+            _ => Err(format!("unknown target: {}", s)),
+        }
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Target::Forth => "forth",
+            Target::C => "c",
+            Target::Rust => "rust",
+            Target::Wat => "wat",
+        };
+        write!(f, "{}", name)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Translator {
     output_file: String,
     buffer: String,
+    // The words the prelude defines, in upper case.
+    prelude_words: HashSet<String>,
 }
 
 impl Translator {
@@ -15,6 +103,7 @@ impl Translator {
         let mut t = Self {
             output_file,
             buffer: String::new(),
+            prelude_words: defined_words(PRELUDE),
         };
 
         // add the contents of base.f to the buffer
@@ -32,29 +121,210 @@ impl Translator {
         }
     }
 
-    pub fn flush(&mut self) {
-        let path = Path::new(&self.output_file);
-        let display = path.display();
-        let mut file = match File::create(&path) {
-            Err(why) => panic!("couldn't create {}: {}", display, why),
-            Ok(file) => file,
+    pub fn flush(&mut self) -> Result<()> {
+        save(&self.output_file, &self.buffer)?;
+        trace!("flushing buffer");
+        Ok(())
+    }
+
+    /// Translates the whole program, following every path through its control flow graph
+    /// (see [`ControlFlowGraph`]) rather than the one a single run takes. The generated
+    /// program is a state machine: `PIET-STATE` holds the reachable (block, DP, CC) state
+    /// the program is in, and `PIET-STEP` runs the command on the way out of it and moves
+    /// on to the next one, until the program terminates. The DP and CC are also kept in
+    /// `PIET-DP` and `PIET-CC`, which `Pointer` and `Switch` change at run time to pick
+    /// the next state.
+    pub fn translate(&mut self, grid: &Grid) -> Result<()> {
+        let graph = BlockGraph::compile(grid);
+        let cfg = ControlFlowGraph::build(grid, &graph)?;
+        self.write_line("VARIABLE PIET-STATE");
+        self.write_line("");
+        self.write_line(": PIET-STEP ( -- )");
+        self.write_line("  PIET-STATE @ CASE");
+        for (index, node) in cfg.nodes().iter().enumerate() {
+            let code = self.node_code(node)?;
+            let block = graph.block(node.state.block);
+            let (x, y) = block.codels[0];
+            self.write_line(&format!(
+                "    \\ ({}, {}) {:?}, DP {:?}, CC {:?}",
+                x, y, block.color.name, node.state.direction_pointer, node.state.codel_chooser
+            ));
+            self.write_line(&format!("    {} OF {} ENDOF", index, code));
+        }
+        self.write_line("  ENDCASE ;");
+        self.write_line("");
+        self.write_line(": PIET-RUN ( -- )");
+        match cfg.entry() {
+            Some(entry) => {
+                let state = cfg.nodes()[entry].state;
+                self.write_line(&format!(
+                    "  {} PIET-DP ! {} PIET-CC !",
+                    direction_number(state.direction_pointer),
+                    codel_chooser_number(state.codel_chooser)
+                ));
+                self.write_line(&format!("  {} PIET-STATE !", entry));
+            }
+            None => self.write_line("  -1 PIET-STATE !"),
+        }
+        self.write_line("  BEGIN PIET-STATE @ 0< 0= WHILE PIET-STEP REPEAT ;");
+        self.write_line("");
+        self.write_line("PIET-RUN");
+        debug!("Translated {} states", cfg.nodes().len());
+        self.check()
+    }
+
+    // The code run in a node: keeping `PIET-DP` and `PIET-CC` up to date with the way the
+    // interpreter turned to get out of the block, the command, and then choosing the next
+    // node (-1 to terminate).
+    fn node_code(&self, node: &Node) -> Result<String> {
+        let edge = match &node.edge {
+            Some(edge) => edge,
+            None => return Ok("-1 PIET-STATE !".to_string()),
         };
-        match file.write_all(self.buffer.as_bytes()) {
-            Err(why) => panic!("couldn't write to {}: {}", display, why),
-            Ok(_) => debug!("successfully wrote to {}", display),
+        let mut code = String::new();
+        let (from, to) = (node.state, edge.to);
+        if to.direction_pointer != from.direction_pointer {
+            let number = direction_number(to.direction_pointer);
+            code.push_str(&format!("{} PIET-DP ! ", number));
         }
-        trace!("flushing buffer");
+        if to.codel_chooser != from.codel_chooser {
+            let number = codel_chooser_number(to.codel_chooser);
+            code.push_str(&format!("{} PIET-CC ! ", number));
+        }
+        if let Some(command) = edge.command {
+            code.push_str(&command.to_forth(edge.value)?);
+            code.push(' ');
+        }
+        match edge.command {
+            // Successor `k` is the one with the DP turned clockwise `k` times.
+            Some(Command::Pointer) => {
+                let first = direction_number(to.direction_pointer);
+                let cases = edge
+                    .successors
+                    .iter()
+                    .enumerate()
+                    .map(|(turns, successor)| {
+                        format!("{} OF {} ENDOF ", (first + turns) % 4, successor)
+                    })
+                    .collect::<String>();
+                code.push_str(&format!("PIET-DP @ CASE {}ENDCASE ", cases));
+            }
+            // Successor 1 is the one with the CC toggled.
+            Some(Command::Switch) => {
+                let first = codel_chooser_number(to.codel_chooser);
+                code.push_str(&format!(
+                    "PIET-CC @ {} = IF {} ELSE {} THEN ",
+                    first, edge.successors[0], edge.successors[1]
+                ));
+            }
+            _ => code.push_str(&format!("{} ", edge.successors[0])),
+        }
+        code.push_str("PIET-STATE !");
+        Ok(code)
     }
 
-    pub fn add_command(&mut self, command: &Command, context: &mut PietProgram) {
-        let forth_command = command.to_forth(context);
-        trace!("Adding command to str buffer: {:?}", forth_command);
-        self.write(&forth_command);
+    fn write_line(&mut self, line: &str) {
+        self.buffer.push_str(line);
+        self.buffer.push('\n');
     }
 
     fn add_base(&mut self) {
-        let bytes = include_bytes!("base.f");
-        let base = String::from_utf8_lossy(bytes);
-        self.write(&base);
+        self.write(PRELUDE);
+    }
+
+    /// Checks that every word in the code written after the prelude is a number, a word
+    /// the prelude or the code itself defines, or one of the few standard words the
+    /// translator uses, so that the output loads in any Forth system the prelude does.
+    pub fn check(&self) -> Result<()> {
+        let code = &self.buffer[PRELUDE.len()..];
+        let defined = defined_words(code);
+        for word in words(code) {
+            let upper = word.to_uppercase();
+            let known = word.parse::<i64>().is_ok()
+                || STANDARD_WORDS.contains(&upper.as_str())
+                || self.prelude_words.contains(&upper)
+                || defined.contains(&upper);
+            if !known {
+                return Err(PietError::Translation(format!(
+                    "the generated code uses {}, which the prelude doesn't define",
+                    word
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+// The words of some Forth source, leaving out comments.
+fn words(source: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    for line in source.lines() {
+        let mut tokens = line.split_whitespace();
+        while let Some(token) = tokens.next() {
+            match token {
+                "\\" => break,
+                "(" => {
+                    for token in tokens.by_ref() {
+                        if token.ends_with(')') {
+                            break;
+                        }
+                    }
+                }
+                _ => words.push(token),
+            }
+        }
+    }
+    words
+}
+
+// The names of the colon definitions and variables in some Forth source, in upper case.
+fn defined_words(source: &str) -> HashSet<String> {
+    words(source)
+        .windows(2)
+        .filter(|pair| pair[0] == ":" || pair[0].eq_ignore_ascii_case("VARIABLE"))
+        .map(|pair| pair[1].to_uppercase())
+        .collect()
+}
+
+// Writes translated code to `output_file`.
+fn save(output_file: &str, code: &str) -> Result<()> {
+    let path = Path::new(output_file);
+    let display = path.display();
+    let mut file = match File::create(path) {
+        Err(why) => {
+            return Err(PietError::Translation(format!(
+                "couldn't create {}: {}",
+                display, why
+            )))
+        }
+        Ok(file) => file,
+    };
+    match file.write_all(code.as_bytes()) {
+        Err(why) => {
+            return Err(PietError::Translation(format!(
+                "couldn't write to {}: {}",
+                display, why
+            )))
+        }
+        Ok(_) => debug!("successfully wrote to {}", display),
+    }
+    Ok(())
+}
+
+// The numbers the generated code keeps the DP in: its clockwise turns from pointing right.
+pub(crate) fn direction_number(direction: Direction) -> usize {
+    match direction {
+        Direction::Right => 0,
+        Direction::Down => 1,
+        Direction::Left => 2,
+        Direction::Up => 3,
+    }
+}
+
+// The numbers the generated code keeps the CC in.
+pub(crate) fn codel_chooser_number(codel_chooser: CodelChooser) -> usize {
+    match codel_chooser {
+        CodelChooser::Left => 0,
+        CodelChooser::Right => 1,
     }
 }
@@ -0,0 +1,103 @@
+//! Translation to a single C99 file that can be compiled with any C compiler.
+
+use crate::cfg::{ControlFlowGraph, Node};
+use crate::command::Command;
+use crate::error::Result;
+use crate::graph::BlockGraph;
+use crate::loader::Grid;
+use crate::translator::{codel_chooser_number, direction_number};
+
+// The runtime every translation starts with: the stack, the DP and CC, and one function
+// per command.
+const RUNTIME: &str = include_str!("runtime.c");
+
+/// Translates the whole program to C, following every path through its control flow graph
+/// like [`Translator::translate`](crate::translator::Translator::translate) does. `main`
+/// is a loop around a `switch` on the reachable (block, DP, CC) state the program is in,
+/// whose cases run the command on the way out of the state and pick the next one. The
+/// stack holds 64-bit values and grows as needed.
+pub fn translate(grid: &Grid) -> Result<String> {
+    let graph = BlockGraph::compile(grid);
+    let cfg = ControlFlowGraph::build(grid, &graph)?;
+    let mut code = String::from(RUNTIME);
+    code.push_str("\nint main(void) {\n");
+    match cfg.entry() {
+        Some(entry) => {
+            let state = cfg.nodes()[entry].state;
+            code.push_str(&format!(
+                "    int state = {};\n    dp = {};\n    cc = {};\n",
+                entry,
+                direction_number(state.direction_pointer),
+                codel_chooser_number(state.codel_chooser)
+            ));
+        }
+        None => code.push_str("    int state = -1;\n"),
+    }
+    code.push_str("    while (state >= 0) {\n        switch (state) {\n");
+    for (index, node) in cfg.nodes().iter().enumerate() {
+        let block = graph.block(node.state.block);
+        let (x, y) = block.codels[0];
+        code.push_str(&format!(
+            "        /* ({}, {}) {:?}, DP {:?}, CC {:?} */\n",
+            x, y, block.color.name, node.state.direction_pointer, node.state.codel_chooser
+        ));
+        code.push_str(&format!("        case {}:\n", index));
+        for line in node_code(node)? {
+            code.push_str(&format!("            {}\n", line));
+        }
+        code.push_str("            break;\n");
+    }
+    code.push_str("        }\n    }\n    free(stack);\n    return 0;\n}\n");
+    debug!("Translated {} states to C", cfg.nodes().len());
+    Ok(code)
+}
+
+// The statements run in a node: keeping `dp` and `cc` up to date with the way the
+// interpreter turned to get out of the block, the command, and then choosing the next
+// node (-1 to terminate).
+fn node_code(node: &Node) -> Result<Vec<String>> {
+    let edge = match &node.edge {
+        Some(edge) => edge,
+        None => return Ok(vec!["state = -1;".to_string()]),
+    };
+    let mut lines = Vec::new();
+    let (from, to) = (node.state, edge.to);
+    if to.direction_pointer != from.direction_pointer {
+        lines.push(format!("dp = {};", direction_number(to.direction_pointer)));
+    }
+    if to.codel_chooser != from.codel_chooser {
+        lines.push(format!("cc = {};", codel_chooser_number(to.codel_chooser)));
+    }
+    if let Some(command) = edge.command {
+        lines.push(command.to_c(edge.value)?);
+    }
+    match edge.command {
+        // Successor `k` is the one with the DP turned clockwise `k` times.
+        Some(Command::Pointer) => {
+            let first = direction_number(to.direction_pointer);
+            let cases = edge
+                .successors
+                .iter()
+                .enumerate()
+                .map(|(turns, successor)| {
+                    format!(
+                        "case {}: state = {}; break; ",
+                        (first + turns) % 4,
+                        successor
+                    )
+                })
+                .collect::<String>();
+            lines.push(format!("switch (dp) {{ {}}}", cases));
+        }
+        // Successor 1 is the one with the CC toggled.
+        Some(Command::Switch) => {
+            let first = codel_chooser_number(to.codel_chooser);
+            lines.push(format!(
+                "state = cc == {} ? {} : {};",
+                first, edge.successors[0], edge.successors[1]
+            ));
+        }
+        _ => lines.push(format!("state = {};", edge.successors[0])),
+    }
+    Ok(lines)
+}
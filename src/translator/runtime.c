@@ -0,0 +1,254 @@
+/* Standard subroutines to copy Piet functionality.
+ * Each command is a function that behaves like the interpreter's Command::execute: a
+ * command that needs more values than the stack holds does nothing, and so do division by
+ * zero and invalid rolls. Arithmetic wraps around at 64 bits. The command functions aren't
+ * static, so that compilers don't warn about the ones a program doesn't use. */
+
+#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+static int64_t *stack;
+static size_t depth, capacity;
+
+/* The DP as clockwise turns from pointing right, and the CC as 0 for left, 1 for right. */
+static int dp, cc;
+
+/* A byte of input that was read ahead, or EOF. */
+static int pending = EOF;
+
+static void push(int64_t value) {
+    if (depth == capacity) {
+        capacity = capacity ? capacity * 2 : 64;
+        stack = realloc(stack, capacity * sizeof *stack);
+        if (!stack) {
+            fputs("out of memory\n", stderr);
+            exit(1);
+        }
+    }
+    stack[depth++] = value;
+}
+
+/* Signed overflow is undefined in C, so the arithmetic is done on unsigned values. */
+static int64_t wrap(uint64_t value) {
+    return value <= INT64_MAX ? (int64_t)value : -(int64_t)(UINT64_MAX - value) - 1;
+}
+
+void piet_pop(void) {
+    if (depth < 1) return;
+    depth--;
+}
+
+void piet_add(void) {
+    if (depth < 2) return;
+    stack[depth - 2] = wrap((uint64_t)stack[depth - 2] + (uint64_t)stack[depth - 1]);
+    depth--;
+}
+
+void piet_subtract(void) {
+    if (depth < 2) return;
+    stack[depth - 2] = wrap((uint64_t)stack[depth - 2] - (uint64_t)stack[depth - 1]);
+    depth--;
+}
+
+void piet_multiply(void) {
+    if (depth < 2) return;
+    stack[depth - 2] = wrap((uint64_t)stack[depth - 2] * (uint64_t)stack[depth - 1]);
+    depth--;
+}
+
+/* C's / and % truncate, as the interpreter does. INT64_MIN / -1 overflows, so -1 is a
+   special case that wraps. */
+void piet_divide(void) {
+    int64_t a, b;
+    if (depth < 2 || stack[depth - 1] == 0) return;
+    a = stack[depth - 2];
+    b = stack[depth - 1];
+    stack[depth - 2] = b == -1 ? wrap(0 - (uint64_t)a) : a / b;
+    depth--;
+}
+
+void piet_mod(void) {
+    int64_t a, b;
+    if (depth < 2 || stack[depth - 1] == 0) return;
+    a = stack[depth - 2];
+    b = stack[depth - 1];
+    stack[depth - 2] = b == -1 ? 0 : a % b;
+    depth--;
+}
+
+void piet_not(void) {
+    if (depth < 1) return;
+    stack[depth - 1] = stack[depth - 1] == 0;
+}
+
+void piet_greater(void) {
+    if (depth < 2) return;
+    stack[depth - 2] = stack[depth - 2] > stack[depth - 1];
+    depth--;
+}
+
+void piet_pointer(void) {
+    if (depth < 1) return;
+    depth--;
+    dp = (int)((dp + (stack[depth] % 4 + 4) % 4) % 4);
+}
+
+void piet_switch(void) {
+    if (depth < 1) return;
+    depth--;
+    if ((stack[depth] % 2 + 2) % 2 == 1) cc ^= 1;
+}
+
+void piet_duplicate(void) {
+    if (depth < 1) return;
+    push(stack[depth - 1]);
+}
+
+static void reverse(int64_t *values, size_t count) {
+    size_t i;
+    for (i = 0; i < count / 2; i++) {
+        int64_t value = values[i];
+        values[i] = values[count - 1 - i];
+        values[count - 1 - i] = value;
+    }
+}
+
+/* Buries the top value of the top depth values rolls times; negative rolls go the other
+ * way. A negative depth or one deeper than the stack pops the arguments and does nothing. */
+void piet_roll(void) {
+    int64_t roll_depth, rolls;
+    size_t count, shift;
+    int64_t *values;
+    if (depth < 2) return;
+    roll_depth = stack[depth - 2];
+    rolls = stack[depth - 1];
+    depth -= 2;
+    if (roll_depth <= 0 || (uint64_t)roll_depth > depth) return;
+    count = (size_t)roll_depth;
+    shift = (size_t)((rolls % roll_depth + roll_depth) % roll_depth);
+    values = stack + depth - count;
+    reverse(values, count);
+    reverse(values, shift);
+    reverse(values + shift, count - shift);
+}
+
+static int read_byte(void) {
+    int byte = pending;
+    if (byte != EOF) {
+        pending = EOF;
+        return byte;
+    }
+    /* Show any prompt before waiting for input. */
+    fflush(stdout);
+    return getchar();
+}
+
+static int is_space(int c) {
+    return c == ' ' || c == '\t' || c == '\n' || c == '\f' || c == '\r';
+}
+
+static int is_digit(int c) {
+    return c >= '0' && c <= '9';
+}
+
+/* Reads a decimal number with an optional sign after skipping whitespace, and leaves the
+ * character after it in the input. Pushes nothing at the end of the input or if the input
+ * isn't a number that fits in 64 bits. */
+void piet_in_number(void) {
+    int c, negative = 0, digits = 0, valid = 1;
+    uint64_t magnitude = 0, limit;
+    do {
+        c = read_byte();
+    } while (is_space(c));
+    if (c == EOF) return;
+    if (c == '-') {
+        negative = 1;
+    } else if (is_digit(c)) {
+        magnitude = (uint64_t)(c - '0');
+        digits = 1;
+    } else if (c != '+') {
+        valid = 0;
+    }
+    limit = negative ? (uint64_t)INT64_MAX + 1 : (uint64_t)INT64_MAX;
+    while (is_digit(c = read_byte())) {
+        uint64_t digit = (uint64_t)(c - '0');
+        if (magnitude > (limit - digit) / 10) valid = 0;
+        else magnitude = magnitude * 10 + digit;
+        digits++;
+    }
+    pending = c;
+    if (!valid || !digits) return;
+    push(negative ? wrap(0 - magnitude) : (int64_t)magnitude);
+}
+
+/* Reads a UTF-8 encoded character and pushes its code point, or the value of its first
+ * byte if it isn't valid UTF-8. Pushes nothing at the end of the input. */
+void piet_in_char(void) {
+    int first = read_byte(), length, i, valid = 1;
+    int64_t code_point, minimum;
+    if (first == EOF) return;
+    if (first >= 0xC0 && first < 0xE0) {
+        code_point = first & 0x1F;
+        length = 1;
+        minimum = 0x80;
+    } else if (first >= 0xE0 && first < 0xF0) {
+        code_point = first & 0x0F;
+        length = 2;
+        minimum = 0x800;
+    } else if (first >= 0xF0 && first < 0xF8) {
+        code_point = first & 0x07;
+        length = 3;
+        minimum = 0x10000;
+    } else {
+        push(first);
+        return;
+    }
+    for (i = 0; i < length; i++) {
+        int byte = read_byte();
+        if (byte == EOF) {
+            valid = 0;
+            break;
+        }
+        if ((byte & 0xC0) != 0x80) valid = 0;
+        code_point = code_point << 6 | (byte & 0x3F);
+    }
+    if (code_point < minimum || (code_point >= 0xD800 && code_point < 0xE000) ||
+        code_point > 0x10FFFF) {
+        valid = 0;
+    }
+    push(valid ? code_point : first);
+}
+
+void piet_out_number(void) {
+    if (depth < 1) return;
+    depth--;
+    printf("%" PRId64, stack[depth]);
+}
+
+/* Writes a code point as UTF-8. Values that aren't code points are popped and ignored. */
+void piet_out_char(void) {
+    int64_t c;
+    if (depth < 1) return;
+    c = stack[--depth];
+    if (c < 0 || c > 0x10FFFF || (c >= 0xD800 && c < 0xE000)) return;
+    if (c < 0x80) {
+        putchar((int)c);
+    } else if (c < 0x800) {
+        putchar((int)(0xC0 | c >> 6));
+        putchar((int)(0x80 | (c & 0x3F)));
+    } else if (c < 0x10000) {
+        putchar((int)(0xE0 | c >> 12));
+        putchar((int)(0x80 | (c >> 6 & 0x3F)));
+        putchar((int)(0x80 | (c & 0x3F)));
+    } else {
+        putchar((int)(0xF0 | c >> 18));
+        putchar((int)(0x80 | (c >> 12 & 0x3F)));
+        putchar((int)(0x80 | (c >> 6 & 0x3F)));
+        putchar((int)(0x80 | (c & 0x3F)));
+    }
+}
+
+/* THE FOLLOWING CODE WAS GENERATED BY PIET TOOL */
+/* DO NOT EDIT */
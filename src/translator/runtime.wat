@@ -0,0 +1,438 @@
+;; Standard subroutines to copy Piet functionality.
+;; Each command is a function that behaves like the interpreter's Command::execute: a
+;; command that needs more values than the stack holds does nothing, and so do division by
+;; zero and invalid rolls. Arithmetic wraps around at 64 bits.
+;;
+;; The host provides the input and output:
+;; - getc returns the code point of the next UTF-8 encoded character of input, the value of
+;;   its first byte if it isn't valid UTF-8, or -1 at the end of the input.
+;; - getn skips whitespace and reads a decimal number, returning 1 and the number, or 0 and
+;;   anything if there is no number.
+;; - putc writes a code point as UTF-8, and putn writes a number in decimal.
+(module
+  (import "env" "getc" (func $getc (result i32)))
+  (import "env" "putc" (func $putc (param i32)))
+  (import "env" "getn" (func $getn (result i32 i64)))
+  (import "env" "putn" (func $putn (param i64)))
+
+  ;; The stack, 8 bytes per value from address 0 up. It grows a page at a time.
+  (memory (export "memory") 1)
+  (global $depth (mut i32) (i32.const 0))
+
+  ;; The DP as clockwise turns from pointing right, and the CC as 0 for left, 1 for right.
+  (global $dp (mut i32) (i32.const 0))
+  (global $cc (mut i32) (i32.const 0))
+
+  ;; The address of the value `index` places below the top of the stack.
+  (func $address (param $index i32) (result i32)
+    global.get $depth
+    local.get $index
+    i32.sub
+    i32.const 1
+    i32.sub
+    i32.const 8
+    i32.mul)
+
+  (func $push (param $value i64)
+    global.get $depth
+    i32.const 1
+    i32.add
+    i32.const 8
+    i32.mul
+    memory.size
+    i32.const 65536
+    i32.mul
+    i32.gt_u
+    if
+      i32.const 1
+      memory.grow
+      i32.const -1
+      i32.eq
+      if
+        unreachable
+      end
+    end
+    global.get $depth
+    i32.const 8
+    i32.mul
+    local.get $value
+    i64.store
+    global.get $depth
+    i32.const 1
+    i32.add
+    global.set $depth)
+
+  ;; Removes the top value, which the caller has made sure exists.
+  (func $take (result i64)
+    i32.const 0
+    call $address
+    i64.load
+    global.get $depth
+    i32.const 1
+    i32.sub
+    global.set $depth)
+
+  (func $peek (param $index i32) (result i64)
+    local.get $index
+    call $address
+    i64.load)
+
+  ;; Replaces the top two values with one.
+  (func $replace2 (param $value i64)
+    global.get $depth
+    i32.const 1
+    i32.sub
+    global.set $depth
+    i32.const 0
+    call $address
+    local.get $value
+    i64.store)
+
+  (func $piet_pop
+    global.get $depth
+    i32.eqz
+    if
+      return
+    end
+    call $take
+    drop)
+
+  (func $piet_add
+    global.get $depth
+    i32.const 2
+    i32.lt_u
+    if
+      return
+    end
+    i32.const 1
+    call $peek
+    i32.const 0
+    call $peek
+    i64.add
+    call $replace2)
+
+  (func $piet_subtract
+    global.get $depth
+    i32.const 2
+    i32.lt_u
+    if
+      return
+    end
+    i32.const 1
+    call $peek
+    i32.const 0
+    call $peek
+    i64.sub
+    call $replace2)
+
+  (func $piet_multiply
+    global.get $depth
+    i32.const 2
+    i32.lt_u
+    if
+      return
+    end
+    i32.const 1
+    call $peek
+    i32.const 0
+    call $peek
+    i64.mul
+    call $replace2)
+
+  ;; i64.div_s and i64.rem_s truncate, as the interpreter does. Dividing by -1 is a
+  ;; negation, since i64.div_s traps when that overflows.
+  (func $piet_divide
+    global.get $depth
+    i32.const 2
+    i32.lt_u
+    if
+      return
+    end
+    i32.const 0
+    call $peek
+    i64.eqz
+    if
+      return
+    end
+    i32.const 0
+    call $peek
+    i64.const -1
+    i64.eq
+    if
+      i64.const 0
+      i32.const 1
+      call $peek
+      i64.sub
+      call $replace2
+      return
+    end
+    i32.const 1
+    call $peek
+    i32.const 0
+    call $peek
+    i64.div_s
+    call $replace2)
+
+  (func $piet_mod
+    global.get $depth
+    i32.const 2
+    i32.lt_u
+    if
+      return
+    end
+    i32.const 0
+    call $peek
+    i64.eqz
+    if
+      return
+    end
+    i32.const 1
+    call $peek
+    i32.const 0
+    call $peek
+    i64.rem_s
+    call $replace2)
+
+  (func $piet_not
+    global.get $depth
+    i32.eqz
+    if
+      return
+    end
+    call $take
+    i64.eqz
+    i64.extend_i32_u
+    call $push)
+
+  (func $piet_greater
+    global.get $depth
+    i32.const 2
+    i32.lt_u
+    if
+      return
+    end
+    i32.const 1
+    call $peek
+    i32.const 0
+    call $peek
+    i64.gt_s
+    i64.extend_i32_u
+    call $replace2)
+
+  ;; The value modulo `modulus`, between 0 and `modulus` - 1.
+  (func $rem_euclid (param $value i64) (param $modulus i64) (result i64)
+    local.get $value
+    local.get $modulus
+    i64.rem_s
+    local.get $modulus
+    i64.add
+    local.get $modulus
+    i64.rem_s)
+
+  (func $piet_pointer
+    global.get $depth
+    i32.eqz
+    if
+      return
+    end
+    call $take
+    i64.const 4
+    call $rem_euclid
+    i32.wrap_i64
+    global.get $dp
+    i32.add
+    i32.const 4
+    i32.rem_u
+    global.set $dp)
+
+  (func $piet_switch
+    global.get $depth
+    i32.eqz
+    if
+      return
+    end
+    call $take
+    i64.const 2
+    call $rem_euclid
+    i32.wrap_i64
+    global.get $cc
+    i32.xor
+    global.set $cc)
+
+  (func $piet_duplicate
+    global.get $depth
+    i32.eqz
+    if
+      return
+    end
+    i32.const 0
+    call $peek
+    call $push)
+
+  ;; Reverses the `count` values from address `from` up.
+  (func $reverse (param $from i32) (param $count i32)
+    (local $to i32)
+    (local $value i64)
+    local.get $count
+    i32.eqz
+    if
+      return
+    end
+    local.get $from
+    local.get $count
+    i32.const 1
+    i32.sub
+    i32.const 8
+    i32.mul
+    i32.add
+    local.set $to
+    block $done
+      loop $swap
+        local.get $from
+        local.get $to
+        i32.ge_u
+        br_if $done
+        local.get $from
+        i64.load
+        local.set $value
+        local.get $from
+        local.get $to
+        i64.load
+        i64.store
+        local.get $to
+        local.get $value
+        i64.store
+        local.get $from
+        i32.const 8
+        i32.add
+        local.set $from
+        local.get $to
+        i32.const 8
+        i32.sub
+        local.set $to
+        br $swap
+      end
+    end)
+
+  ;; Buries the top value of the top depth values rolls times; negative rolls go the other
+  ;; way. A negative depth or one deeper than the stack pops the arguments and does nothing.
+  (func $piet_roll
+    (local $rolls i64)
+    (local $depth i64)
+    (local $count i32)
+    (local $shift i32)
+    (local $from i32)
+    global.get $depth
+    i32.const 2
+    i32.lt_u
+    if
+      return
+    end
+    call $take
+    local.set $rolls
+    call $take
+    local.set $depth
+    local.get $depth
+    i64.const 0
+    i64.le_s
+    local.get $depth
+    global.get $depth
+    i64.extend_i32_u
+    i64.gt_s
+    i32.or
+    if
+      return
+    end
+    local.get $depth
+    i32.wrap_i64
+    local.set $count
+    local.get $rolls
+    local.get $depth
+    call $rem_euclid
+    i32.wrap_i64
+    local.set $shift
+    local.get $count
+    i32.const 1
+    i32.sub
+    call $address
+    local.set $from
+    local.get $from
+    local.get $count
+    call $reverse
+    local.get $from
+    local.get $shift
+    call $reverse
+    local.get $from
+    local.get $shift
+    i32.const 8
+    i32.mul
+    i32.add
+    local.get $count
+    local.get $shift
+    i32.sub
+    call $reverse)
+
+  (func $piet_in_number
+    (local $number i64)
+    call $getn
+    local.set $number
+    if
+      local.get $number
+      call $push
+    end)
+
+  (func $piet_in_char
+    (local $c i32)
+    call $getc
+    local.tee $c
+    i32.const 0
+    i32.lt_s
+    if
+      return
+    end
+    local.get $c
+    i64.extend_i32_u
+    call $push)
+
+  (func $piet_out_number
+    global.get $depth
+    i32.eqz
+    if
+      return
+    end
+    call $take
+    call $putn)
+
+  ;; Values that aren't code points are popped and ignored.
+  (func $piet_out_char
+    (local $c i64)
+    global.get $depth
+    i32.eqz
+    if
+      return
+    end
+    call $take
+    local.tee $c
+    i64.const 0
+    i64.lt_s
+    local.get $c
+    i64.const 0x10FFFF
+    i64.gt_s
+    i32.or
+    local.get $c
+    i64.const 0xD800
+    i64.ge_s
+    local.get $c
+    i64.const 0xE000
+    i64.lt_s
+    i32.and
+    i32.or
+    if
+      return
+    end
+    local.get $c
+    i32.wrap_i64
+    call $putc)
+
+  ;; THE FOLLOWING CODE WAS GENERATED BY PIET TOOL
+  ;; DO NOT EDIT
@@ -0,0 +1,123 @@
+//! Translation to a Rust source file that runs the program through any [`PietIo`]. The
+//! file uses this crate, so it only builds in a crate that depends on `piet_tool`.
+//!
+//! [`PietIo`]: crate::io::PietIo
+
+use crate::cfg::{ControlFlowGraph, Node};
+use crate::command::Command;
+use crate::error::Result;
+use crate::graph::BlockGraph;
+use crate::loader::Grid;
+use crate::translator::{codel_chooser_number, direction_number};
+
+// The runtime every translation starts with: a machine holding the stack, the DP and CC,
+// with one method per command.
+const RUNTIME: &str = include_str!("runtime.rs.in");
+
+/// Translates the whole program to Rust, following every path through its control flow
+/// graph like [`Translator::translate`](crate::translator::Translator::translate) does.
+/// The file defines `pub fn run(io: &mut impl PietIo) -> std::io::Result<()>`, which
+/// loops over a `match` on the reachable (block, DP, CC) state the program is in. It
+/// depends on this crate for [`Stack`](crate::stack::Stack), [`PietIo`](crate::io::PietIo)
+/// and the arithmetic of [`PietValue`](crate::value::PietValue), so it behaves like the
+/// interpreter with wrapping arithmetic.
+pub fn translate(grid: &Grid) -> Result<String> {
+    let graph = BlockGraph::compile(grid);
+    let cfg = ControlFlowGraph::build(grid, &graph)?;
+    let mut code = String::from(RUNTIME);
+    code.push_str(
+        "\n/// Runs the program, reading its input from and writing its output to `io`.\n",
+    );
+    code.push_str("pub fn run(io: &mut impl PietIo) -> io::Result<()> {\n");
+    code.push_str("    let mut m = Machine::new(io);\n");
+    match cfg.entry() {
+        Some(entry) => {
+            let state = cfg.nodes()[entry].state;
+            code.push_str(&format!(
+                "    m.dp = {};\n    m.cc = {};\n    let mut state = Some({});\n",
+                direction_number(state.direction_pointer),
+                codel_chooser_number(state.codel_chooser),
+                entry
+            ));
+        }
+        None => code.push_str("    let mut state = None;\n"),
+    }
+    code.push_str("    while let Some(current) = state {\n        state = match current {\n");
+    for (index, node) in cfg.nodes().iter().enumerate() {
+        let block = graph.block(node.state.block);
+        let (x, y) = block.codels[0];
+        code.push_str(&format!(
+            "            // ({}, {}) {:?}, DP {:?}, CC {:?}\n",
+            x, y, block.color.name, node.state.direction_pointer, node.state.codel_chooser
+        ));
+        let lines = node_code(node)?;
+        // Laid out the way rustfmt would, so that the file can be checked in as it is.
+        if let [next] = lines.as_slice() {
+            code.push_str(&format!("            {} => {},\n", index, next));
+            continue;
+        }
+        code.push_str(&format!("            {} => {{\n", index));
+        for line in lines {
+            code.push_str(&format!("                {}\n", line));
+        }
+        code.push_str("            }\n");
+    }
+    code.push_str("            _ => unreachable!(),\n        };\n    }\n    m.io.flush()\n}\n");
+    debug!("Translated {} states to Rust", cfg.nodes().len());
+    Ok(code)
+}
+
+// The statements run in a node: keeping `m.dp` and `m.cc` up to date with the way the
+// interpreter turned to get out of the block, the command, and then the next node (`None`
+// to terminate) as the value of the block.
+fn node_code(node: &Node) -> Result<Vec<String>> {
+    let edge = match &node.edge {
+        Some(edge) => edge,
+        None => return Ok(vec!["None".to_string()]),
+    };
+    let mut lines = Vec::new();
+    let (from, to) = (node.state, edge.to);
+    if to.direction_pointer != from.direction_pointer {
+        lines.push(format!(
+            "m.dp = {};",
+            direction_number(to.direction_pointer)
+        ));
+    }
+    if to.codel_chooser != from.codel_chooser {
+        lines.push(format!(
+            "m.cc = {};",
+            codel_chooser_number(to.codel_chooser)
+        ));
+    }
+    if let Some(command) = edge.command {
+        lines.push(command.to_rust(edge.value)?);
+    }
+    match edge.command {
+        // Successor `k` is the one with the DP turned clockwise `k` times.
+        Some(Command::Pointer) => {
+            let first = direction_number(to.direction_pointer);
+            lines.push("match m.dp {".to_string());
+            for (turns, successor) in edge.successors.iter().enumerate() {
+                // The match has to be exhaustive, so the last case catches the rest.
+                if turns == 3 {
+                    lines.push(format!("    _ => Some({}),", successor));
+                } else {
+                    let dp = (first + turns) % 4;
+                    lines.push(format!("    {} => Some({}),", dp, successor));
+                }
+            }
+            lines.push("}".to_string());
+        }
+        // Successor 1 is the one with the CC toggled.
+        Some(Command::Switch) => {
+            let first = codel_chooser_number(to.codel_chooser);
+            lines.push(format!("if m.cc == {} {{", first));
+            lines.push(format!("    Some({})", edge.successors[0]));
+            lines.push("} else {".to_string());
+            lines.push(format!("    Some({})", edge.successors[1]));
+            lines.push("}".to_string());
+        }
+        _ => lines.push(format!("Some({})", edge.successors[0])),
+    }
+    Ok(lines)
+}
@@ -0,0 +1,129 @@
+//! Translation to a WebAssembly text (WAT) module.
+
+use crate::cfg::{ControlFlowGraph, Node};
+use crate::command::Command;
+use crate::error::Result;
+use crate::graph::BlockGraph;
+use crate::loader::Grid;
+use crate::translator::{codel_chooser_number, direction_number};
+
+// The start of the module every translation begins with: the imports, the stack in linear
+// memory, the DP and CC, and one function per command. The translation adds `run` and
+// closes the module.
+const RUNTIME: &str = include_str!("runtime.wat");
+
+/// Translates the whole program to a WebAssembly module in the text format, following
+/// every path through its control flow graph like
+/// [`Translator::translate`](crate::translator::Translator::translate) does.
+///
+/// The module imports four functions from `env`: `getc` (`() -> i32`) returns the next
+/// character of input, or -1 at the end of the input, and `getn` (`() -> (i32, i64)`)
+/// returns 1 and the next number of input, or 0 if there isn't one. `putc` (`(i32)`) and
+/// `putn` (`(i64)`) write a character and a number. It exports its `memory`, which holds
+/// the stack, and `run`, which runs the program: a loop around a `br_table` on the
+/// reachable (block, DP, CC) state the program is in. Arithmetic wraps around at 64 bits.
+pub fn translate(grid: &Grid) -> Result<String> {
+    let graph = BlockGraph::compile(grid);
+    let cfg = ControlFlowGraph::build(grid, &graph)?;
+    let nodes = cfg.nodes();
+    let mut code = String::from(RUNTIME);
+    code.push_str("\n  (func (export \"run\")\n    (local $state i32)\n");
+    match cfg.entry() {
+        Some(entry) => {
+            let state = nodes[entry].state;
+            for line in [
+                format!("i32.const {}", direction_number(state.direction_pointer)),
+                "global.set $dp".to_string(),
+                format!("i32.const {}", codel_chooser_number(state.codel_chooser)),
+                "global.set $cc".to_string(),
+                format!("i32.const {}", entry),
+            ] {
+                code.push_str(&format!("    {}\n", line));
+            }
+        }
+        None => code.push_str("    i32.const -1\n"),
+    }
+    code.push_str("    local.set $state\n    block $done\n      loop $next\n");
+    // One block per node, innermost first, so that branching out of the block of node `n`
+    // lands on the code of node `n`. Any other state, such as -1, terminates.
+    for index in (0..nodes.len()).rev() {
+        code.push_str(&format!("        block $s{}\n", index));
+    }
+    let targets = (0..nodes.len())
+        .map(|index| format!("$s{} ", index))
+        .collect::<String>();
+    code.push_str("        local.get $state\n");
+    code.push_str(&format!("        br_table {}$done\n", targets));
+    for (index, node) in nodes.iter().enumerate() {
+        let block = graph.block(node.state.block);
+        let (x, y) = block.codels[0];
+        code.push_str(&format!(
+            "        end\n        ;; {}: ({}, {}) {:?}, DP {:?}, CC {:?}\n",
+            index, x, y, block.color.name, node.state.direction_pointer, node.state.codel_chooser
+        ));
+        for line in node_code(node)? {
+            code.push_str(&format!("        {}\n", line));
+        }
+        code.push_str("        local.set $state\n        br $next\n");
+    }
+    code.push_str("      end\n    end)\n)\n");
+    debug!("Translated {} states to WebAssembly", nodes.len());
+    Ok(code)
+}
+
+// The instructions run in a node: keeping `$dp` and `$cc` up to date with the way the
+// interpreter turned to get out of the block, the command, and then pushing the next node
+// (-1 to terminate).
+fn node_code(node: &Node) -> Result<Vec<String>> {
+    let edge = match &node.edge {
+        Some(edge) => edge,
+        None => return Ok(vec!["i32.const -1".to_string()]),
+    };
+    let mut lines = Vec::new();
+    let (from, to) = (node.state, edge.to);
+    if to.direction_pointer != from.direction_pointer {
+        lines.push(format!(
+            "i32.const {}",
+            direction_number(to.direction_pointer)
+        ));
+        lines.push("global.set $dp".to_string());
+    }
+    if to.codel_chooser != from.codel_chooser {
+        lines.push(format!(
+            "i32.const {}",
+            codel_chooser_number(to.codel_chooser)
+        ));
+        lines.push("global.set $cc".to_string());
+    }
+    if let Some(command) = edge.command {
+        lines.extend(command.to_wat(edge.value)?.lines().map(String::from));
+    }
+    match edge.command {
+        // Successor `k` is the one with the DP turned clockwise `k` times. Each `select`
+        // keeps the successor below it if the DP matches and the one chosen so far if not.
+        Some(Command::Pointer) => {
+            let first = direction_number(to.direction_pointer);
+            for successor in &edge.successors {
+                lines.push(format!("i32.const {}", successor));
+            }
+            for turns in (0..3).rev() {
+                lines.push("global.get $dp".to_string());
+                lines.push(format!("i32.const {}", (first + turns) % 4));
+                lines.push("i32.eq".to_string());
+                lines.push("select".to_string());
+            }
+        }
+        // Successor 1 is the one with the CC toggled.
+        Some(Command::Switch) => {
+            let first = codel_chooser_number(to.codel_chooser);
+            lines.push(format!("i32.const {}", edge.successors[0]));
+            lines.push(format!("i32.const {}", edge.successors[1]));
+            lines.push("global.get $cc".to_string());
+            lines.push(format!("i32.const {}", first));
+            lines.push("i32.eq".to_string());
+            lines.push("select".to_string());
+        }
+        _ => lines.push(format!("i32.const {}", edge.successors[0])),
+    }
+    Ok(lines)
+}
@@ -0,0 +1,445 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use ratatui::{
+    buffer::Buffer,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style},
+    text::Line,
+    widgets::{Block, Paragraph, Widget, Wrap},
+    DefaultTerminal, Frame,
+};
+
+use crate::{
+    color::contrast,
+    error::Result,
+    interpreter::{Direction, PietProgram, DEFAULT_HISTORY_LIMIT},
+    io::BufferIo,
+    value::PietValue,
+};
+
+// How many executed commands are kept for the commands pane.
+const RECENT_COMMANDS: usize = 200;
+// The most steps executed between two frames, so that the UI stays responsive.
+const MAX_STEPS_PER_FRAME: u64 = 100_000;
+const MIN_ZOOM: i32 = -5;
+const MAX_ZOOM: i32 = 4;
+
+const HELP: &str =
+    "space run/pause  s step  b back  +/- speed  arrows pan  z/x zoom in/out  c center  q quit";
+
+/// Shows a program in a full-screen terminal UI and lets the user step through it, run it
+/// at a chosen speed and step back over the last [`DEFAULT_HISTORY_LIMIT`] steps. Returns
+/// when the user quits.
+///
+/// The program's output is shown in a pane, so it has to run on a [`BufferIo`].
+pub fn run<V: PietValue>(mut program: PietProgram<BufferIo, V>) -> Result<()> {
+    program.set_history_limit(DEFAULT_HISTORY_LIMIT);
+    let mut terminal = ratatui::try_init()?;
+    let result = App::new(program).run(&mut terminal);
+    ratatui::try_restore()?;
+    result
+}
+
+struct App<V: PietValue> {
+    program: PietProgram<BufferIo, V>,
+    running: bool,
+    // Steps per second while running.
+    speed: u64,
+    view: Viewport,
+    // Whether the view scrolls to keep the interpreter in sight.
+    follow: bool,
+    // The commands executed most recently, newest last, with the step they were executed in.
+    recent: VecDeque<(u64, String)>,
+    message: String,
+    quit: bool,
+}
+
+impl<V: PietValue> App<V> {
+    fn new(program: PietProgram<BufferIo, V>) -> Self {
+        App {
+            program,
+            running: false,
+            speed: 10,
+            view: Viewport::default(),
+            follow: true,
+            recent: VecDeque::new(),
+            message: String::new(),
+            quit: false,
+        }
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        let mut last_frame = Instant::now();
+        // Steps owed at the current speed but not executed yet, as a fraction.
+        let mut owed = 0.0;
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            let timeout = if self.running {
+                Duration::from_millis(16)
+            } else {
+                Duration::from_millis(250)
+            };
+            if event::poll(timeout)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        self.handle_key(key.code);
+                    }
+                }
+            }
+            let now = Instant::now();
+            if self.running {
+                owed += now.duration_since(last_frame).as_secs_f64() * self.speed as f64;
+                let steps = (owed as u64).min(MAX_STEPS_PER_FRAME);
+                owed -= owed.floor();
+                for _ in 0..steps {
+                    if !self.step() {
+                        self.running = false;
+                        break;
+                    }
+                }
+            } else {
+                owed = 0.0;
+            }
+            last_frame = now;
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char(' ') => {
+                self.running = !self.running && !self.program.is_terminated();
+                self.message.clear();
+            }
+            KeyCode::Char('s') | KeyCode::Enter => {
+                self.running = false;
+                self.message.clear();
+                self.step();
+            }
+            KeyCode::Char('b') | KeyCode::Backspace => {
+                self.running = false;
+                self.step_back();
+            }
+            KeyCode::Char('+') | KeyCode::Char('=') => self.speed = (self.speed * 2).min(1 << 20),
+            KeyCode::Char('-') => self.speed = (self.speed / 2).max(1),
+            KeyCode::Char('z') => self.view.zoom_in(),
+            KeyCode::Char('x') => self.view.zoom_out(),
+            KeyCode::Char('c') => self.follow = true,
+            KeyCode::Left | KeyCode::Char('h') => self.pan(-1, 0),
+            KeyCode::Right | KeyCode::Char('l') => self.pan(1, 0),
+            KeyCode::Up | KeyCode::Char('k') => self.pan(0, -1),
+            KeyCode::Down | KeyCode::Char('j') => self.pan(0, 1),
+            _ => {}
+        }
+    }
+
+    // Executes one step. Returns false if the program can't or shouldn't go on running.
+    fn step(&mut self) -> bool {
+        match self.program.step_once() {
+            Ok(outcome) => {
+                if let (Some(command), Some(to)) = (outcome.command, outcome.to) {
+                    self.recent.push_back((
+                        outcome.step,
+                        format!(
+                            "{:>6} {:?} at ({}, {})",
+                            outcome.step, command, to.position.0, to.position.1
+                        ),
+                    ));
+                    if self.recent.len() > RECENT_COMMANDS {
+                        self.recent.pop_front();
+                    }
+                }
+                if outcome.terminated {
+                    self.message = "The program has terminated.".to_string();
+                    false
+                } else if let Some(number) = outcome.breakpoint {
+                    self.message = format!("Breakpoint {} hit.", number);
+                    false
+                } else {
+                    true
+                }
+            }
+            Err(e) => {
+                self.message = e.to_string();
+                false
+            }
+        }
+    }
+
+    fn step_back(&mut self) {
+        if !self.program.step_back() {
+            self.message = "Reached the start of the recorded history.".to_string();
+            return;
+        }
+        self.message.clear();
+        let steps = self.program.steps();
+        while self.recent.back().is_some_and(|(step, _)| *step >= steps) {
+            self.recent.pop_back();
+        }
+    }
+
+    fn pan(&mut self, dx: i32, dy: i32) {
+        self.follow = false;
+        self.view.pan(dx, dy);
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, help] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+        let [grid_area, side] =
+            Layout::horizontal([Constraint::Min(10), Constraint::Length(42)]).areas(main);
+        let [state_area, stack_area, output_area, commands_area] = Layout::vertical([
+            Constraint::Length(7),
+            Constraint::Percentage(30),
+            Constraint::Percentage(30),
+            Constraint::Fill(1),
+        ])
+        .areas(side);
+
+        let dp = self.program.direction_pointer();
+        let cc = self.program.codel_chooser();
+        let grid_block = Block::bordered().title(format!(
+            " DP {} CC {} ({:?}) ",
+            arrow(dp),
+            arrow(dp.choose_codel(cc)),
+            cc
+        ));
+        let inner = grid_block.inner(grid_area);
+        if self.follow {
+            let size = (inner.width as i32, inner.height as i32 * 2);
+            self.view.keep_in_view(self.program.position(), size);
+        }
+        frame.render_widget(grid_block, grid_area);
+        frame.render_widget(GridView { app: self }, inner);
+
+        let block = self.program.current_block();
+        let state = vec![
+            Line::from(format!(
+                "Step {}  {}",
+                self.program.steps(),
+                if self.running { "running" } else { "paused" }
+            )),
+            Line::from(format!(
+                "At ({}, {}) in {:?} block of {}",
+                block.position.0, block.position.1, block.color.name, block.size
+            )),
+            Line::from(format!("DP {:?}  CC {:?}", dp, cc)),
+            Line::from(format!("Speed {} steps/s", self.speed)),
+            Line::from(format!(
+                "Zoom {}{}",
+                self.view.zoom,
+                if self.follow { "  following" } else { "" }
+            )),
+        ];
+        frame.render_widget(
+            Paragraph::new(state).block(Block::bordered().title(" State ")),
+            state_area,
+        );
+
+        let stack = self.program.stack();
+        let values = stack
+            .values()
+            .iter()
+            .rev()
+            .map(|value| Line::from(value.to_string()))
+            .collect::<Vec<Line>>();
+        frame.render_widget(
+            Paragraph::new(values)
+                .block(Block::bordered().title(format!(" Stack ({}, top first) ", stack.len()))),
+            stack_area,
+        );
+
+        let output = self.program.io().output_string();
+        let width = output_area.width.saturating_sub(2).max(1) as usize;
+        let lines = output
+            .split('\n')
+            .map(|line| line.chars().count().max(1).div_ceil(width))
+            .sum::<usize>();
+        let scroll = lines.saturating_sub(output_area.height.saturating_sub(2) as usize);
+        frame.render_widget(
+            Paragraph::new(output)
+                .wrap(Wrap { trim: false })
+                .scroll((scroll.min(u16::MAX as usize) as u16, 0))
+                .block(Block::bordered().title(" Output ")),
+            output_area,
+        );
+
+        let shown = commands_area.height.saturating_sub(2) as usize;
+        let commands = self
+            .recent
+            .iter()
+            .skip(self.recent.len().saturating_sub(shown))
+            .map(|(_, command)| Line::from(command.as_str()))
+            .collect::<Vec<Line>>();
+        frame.render_widget(
+            Paragraph::new(commands).block(Block::bordered().title(" Commands ")),
+            commands_area,
+        );
+
+        let status = if self.message.is_empty() {
+            HELP
+        } else {
+            &self.message
+        };
+        frame.render_widget(Paragraph::new(status), help);
+    }
+}
+
+/// Which part of the grid the grid pane shows, in half-cells: every cell of the pane is
+/// two half-cells, one above the other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Viewport {
+    /// The codel shown in the top left half-cell.
+    pub origin: (i32, i32),
+    /// Each codel is 2^zoom half-cells wide and high; below 0, each half-cell shows
+    /// 2^-zoom codels across.
+    pub zoom: i32,
+}
+
+impl Viewport {
+    pub fn zoom_in(&mut self) {
+        self.zoom = (self.zoom + 1).min(MAX_ZOOM);
+    }
+
+    pub fn zoom_out(&mut self) {
+        self.zoom = (self.zoom - 1).max(MIN_ZOOM);
+    }
+
+    /// Moves the view by 16 half-cells' worth of codels in each direction given.
+    pub fn pan(&mut self, dx: i32, dy: i32) {
+        let distance = (16 >> self.zoom.max(0)).max(1) << (-self.zoom).max(0);
+        self.origin.0 += dx * distance;
+        self.origin.1 += dy * distance;
+    }
+
+    /// How many half-cells wide a codel is drawn, and how many codels apart the codels
+    /// shown in neighboring half-cells are.
+    pub fn codel_size(&self) -> (i32, i32) {
+        if self.zoom >= 0 {
+            (1 << self.zoom, 1)
+        } else {
+            (1, 1 << -self.zoom)
+        }
+    }
+
+    /// The codel shown in the half-cell `pixel`.
+    pub fn codel_at(&self, pixel: (i32, i32)) -> (i32, i32) {
+        if self.zoom >= 0 {
+            (
+                self.origin.0 + (pixel.0 >> self.zoom),
+                self.origin.1 + (pixel.1 >> self.zoom),
+            )
+        } else {
+            (
+                self.origin.0 + (pixel.0 << -self.zoom),
+                self.origin.1 + (pixel.1 << -self.zoom),
+            )
+        }
+    }
+
+    /// The half-cell showing the middle of `codel`.
+    pub fn pixel_of(&self, codel: (i32, i32)) -> (i32, i32) {
+        let (x, y) = (codel.0 - self.origin.0, codel.1 - self.origin.1);
+        if self.zoom >= 0 {
+            let half = (1 << self.zoom) / 2;
+            ((x << self.zoom) + half, (y << self.zoom) + half)
+        } else {
+            (x >> -self.zoom, y >> -self.zoom)
+        }
+    }
+
+    /// Scrolls a pane of `size` half-cells so that `codel` is in the middle of it, if it's
+    /// out of sight.
+    pub fn keep_in_view(&mut self, codel: (i32, i32), size: (i32, i32)) {
+        let (px, py) = self.pixel_of(codel);
+        if px < 0 || py < 0 || px >= size.0 || py >= size.1 {
+            let (cx, cy) = self.codel_at((size.0 / 2, size.1 / 2));
+            self.origin.0 += codel.0 - cx;
+            self.origin.1 += codel.1 - cy;
+        }
+    }
+}
+
+// Draws the grid with half-block characters, so that every cell shows two codels (or
+// parts of codels) above each other, and outlines the current block.
+struct GridView<'a, V: PietValue> {
+    app: &'a App<V>,
+}
+
+impl<V: PietValue> Widget for GridView<'_, V> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let app = self.app;
+        let program = &app.program;
+        let graph = program.graph();
+        let grid = program.grid();
+        let current = program.current_block();
+        let outline = rgb(contrast(current.color.to_rgb()));
+        let in_grid = |(x, y): (i32, i32)| {
+            x >= 0 && y >= 0 && (y as usize) < grid.len() && (x as usize) < grid[0].len()
+        };
+        let in_block = |codel: (i32, i32)| in_grid(codel) && graph.block_at(codel).id == current.id;
+        // The size of a half-cell's codel in half-cells, and how far apart the codels are
+        // that neighbor it.
+        let (size, reach) = app.view.codel_size();
+        let color_at = |pixel: (i32, i32)| {
+            let codel = app.view.codel_at(pixel);
+            if !in_grid(codel) {
+                return Color::Reset;
+            }
+            if in_block(codel) {
+                let (sx, sy) = (pixel.0 % size, pixel.1 % size);
+                let edge = (sx == 0 && !in_block((codel.0 - reach, codel.1)))
+                    || (sx == size - 1 && !in_block((codel.0 + reach, codel.1)))
+                    || (sy == 0 && !in_block((codel.0, codel.1 - reach)))
+                    || (sy == size - 1 && !in_block((codel.0, codel.1 + reach)));
+                if edge {
+                    return outline;
+                }
+            }
+            rgb(grid[codel.1 as usize][codel.0 as usize].to_rgb())
+        };
+
+        for row in 0..area.height {
+            for column in 0..area.width {
+                let pixel = (column as i32, row as i32 * 2);
+                let top = color_at(pixel);
+                let bottom = color_at((pixel.0, pixel.1 + 1));
+                if let Some(cell) = buf.cell_mut((area.x + column, area.y + row)) {
+                    cell.set_symbol("▀").set_fg(top).set_bg(bottom);
+                }
+            }
+        }
+
+        // Point the DP arrow out of the codel the interpreter will leave the block from.
+        let dp = program.direction_pointer();
+        let exit = graph
+            .block(current.id)
+            .exit(dp, program.codel_chooser())
+            .codel;
+        let (px, py) = app.view.pixel_of(exit);
+        if px >= 0 && py >= 0 && px < area.width as i32 && py < area.height as i32 * 2 {
+            let color = grid[exit.1 as usize][exit.0 as usize].to_rgb();
+            let cell_position = (area.x + px as u16, area.y + (py / 2) as u16);
+            if let Some(cell) = buf.cell_mut(cell_position) {
+                cell.set_symbol(&arrow(dp).to_string())
+                    .set_style(Style::new().fg(rgb(contrast(color))).bg(rgb(color)));
+            }
+        }
+    }
+}
+
+fn arrow(direction: Direction) -> char {
+    match direction {
+        Direction::Right => '→',
+        Direction::Down => '↓',
+        Direction::Left => '←',
+        Direction::Up => '↑',
+    }
+}
+
+fn rgb([r, g, b]: [u8; 3]) -> Color {
+    Color::Rgb(r, g, b)
+}
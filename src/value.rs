@@ -0,0 +1,207 @@
+use std::fmt::{self, Debug, Display};
+use std::hash::Hash;
+use std::str::FromStr;
+
+/// An arithmetic operation performed by a Piet command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Add,
+    Subtract,
+    Multiply,
+    /// Integer division, rounding towards zero.
+    Divide,
+    /// The remainder of [`Operation::Divide`].
+    Remainder,
+}
+
+/// What the interpreter does when the result of an arithmetic command doesn't fit in the
+/// stack's value type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArithmeticMode {
+    /// Wrap around at the bounds of the type, like two's complement hardware.
+    Wrapping,
+    /// Clamp the result to the smallest or largest value of the type.
+    Saturating,
+    /// Stop the program with a runtime error.
+    #[default]
+    Trapping,
+    /// Keep the exact result. This needs an unbounded value type (`BigInt`, with the
+    /// `bigint` feature); on a fixed-width type an overflow is a runtime error.
+    Promote,
+}
+
+impl FromStr for ArithmeticMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrapping" => Ok(ArithmeticMode::Wrapping),
+            "saturating" => Ok(ArithmeticMode::Saturating),
+            "trap" | "trapping" => Ok(ArithmeticMode::Trapping),
+            "promote" => Ok(ArithmeticMode::Promote),
+            _ => Err(format!("unknown arithmetic mode: {}", s)),
+        }
+    }
+}
+
+impl Display for ArithmeticMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ArithmeticMode::Wrapping => "wrapping",
+            ArithmeticMode::Saturating => "saturating",
+            ArithmeticMode::Trapping => "trap",
+            ArithmeticMode::Promote => "promote",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A type that can be stored on the Piet stack.
+///
+/// Implemented for the fixed-width signed integers and, with the `bigint` feature, for
+/// `num_bigint::BigInt`. The interpreter decides what to do when a result doesn't fit,
+/// based on its [`ArithmeticMode`].
+pub trait PietValue:
+    Clone + Debug + Display + FromStr + Eq + Hash + PartialOrd + Send + 'static
+{
+    fn from_i64(value: i64) -> Option<Self>;
+
+    /// Converts the value to an `i64`, if it fits.
+    fn to_i64(&self) -> Option<i64>;
+
+    fn zero() -> Self;
+
+    fn one() -> Self;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::zero()
+    }
+
+    /// Applies `op` to `self` and `other`, or returns `None` if the result doesn't fit.
+    /// `other` is never zero for division and remainder.
+    fn checked(&self, op: Operation, other: &Self) -> Option<Self>;
+
+    /// Applies `op`, wrapping around at the bounds of the type.
+    fn wrapping(&self, op: Operation, other: &Self) -> Self;
+
+    /// Applies `op`, clamping the result to the bounds of the type.
+    fn saturating(&self, op: Operation, other: &Self) -> Self;
+
+    /// The value modulo `modulus`, always in `0..modulus`.
+    fn rem_euclid_u64(&self, modulus: u64) -> u64;
+}
+
+macro_rules! impl_piet_value {
+    ($($t:ty),*) => {$(
+        impl PietValue for $t {
+            fn from_i64(value: i64) -> Option<Self> {
+                <$t>::try_from(value).ok()
+            }
+
+            fn to_i64(&self) -> Option<i64> {
+                i64::try_from(*self).ok()
+            }
+
+            fn zero() -> Self {
+                0
+            }
+
+            fn one() -> Self {
+                1
+            }
+
+            fn checked(&self, op: Operation, other: &Self) -> Option<Self> {
+                match op {
+                    Operation::Add => self.checked_add(*other),
+                    Operation::Subtract => self.checked_sub(*other),
+                    Operation::Multiply => self.checked_mul(*other),
+                    Operation::Divide => self.checked_div(*other),
+                    Operation::Remainder => self.checked_rem(*other),
+                }
+            }
+
+            fn wrapping(&self, op: Operation, other: &Self) -> Self {
+                match op {
+                    Operation::Add => self.wrapping_add(*other),
+                    Operation::Subtract => self.wrapping_sub(*other),
+                    Operation::Multiply => self.wrapping_mul(*other),
+                    Operation::Divide => self.wrapping_div(*other),
+                    Operation::Remainder => self.wrapping_rem(*other),
+                }
+            }
+
+            fn saturating(&self, op: Operation, other: &Self) -> Self {
+                match op {
+                    Operation::Add => self.saturating_add(*other),
+                    Operation::Subtract => self.saturating_sub(*other),
+                    Operation::Multiply => self.saturating_mul(*other),
+                    Operation::Divide => self.saturating_div(*other),
+                    // MIN % -1 is the only remainder that overflows, and its true value is 0.
+                    Operation::Remainder => self.checked_rem(*other).unwrap_or(0),
+                }
+            }
+
+            fn rem_euclid_u64(&self, modulus: u64) -> u64 {
+                (*self as i128).rem_euclid(modulus as i128) as u64
+            }
+        }
+    )*};
+}
+
+impl_piet_value!(i32, i64, i128);
+
+#[cfg(feature = "bigint")]
+mod bigint {
+    use num_bigint::BigInt;
+    use num_integer::Integer;
+    use num_traits::{One, ToPrimitive, Zero};
+
+    use super::{Operation, PietValue};
+
+    impl PietValue for BigInt {
+        fn from_i64(value: i64) -> Option<Self> {
+            Some(BigInt::from(value))
+        }
+
+        fn to_i64(&self) -> Option<i64> {
+            ToPrimitive::to_i64(self)
+        }
+
+        fn zero() -> Self {
+            Zero::zero()
+        }
+
+        fn one() -> Self {
+            One::one()
+        }
+
+        fn is_zero(&self) -> bool {
+            Zero::is_zero(self)
+        }
+
+        fn checked(&self, op: Operation, other: &Self) -> Option<Self> {
+            Some(match op {
+                Operation::Add => self + other,
+                Operation::Subtract => self - other,
+                Operation::Multiply => self * other,
+                Operation::Divide => self / other,
+                Operation::Remainder => self % other,
+            })
+        }
+
+        // A BigInt never overflows, so every mode gives the exact result.
+        fn wrapping(&self, op: Operation, other: &Self) -> Self {
+            PietValue::checked(self, op, other).expect("BigInt arithmetic cannot overflow")
+        }
+
+        fn saturating(&self, op: Operation, other: &Self) -> Self {
+            PietValue::checked(self, op, other).expect("BigInt arithmetic cannot overflow")
+        }
+
+        fn rem_euclid_u64(&self, modulus: u64) -> u64 {
+            self.mod_floor(&BigInt::from(modulus))
+                .to_u64()
+                .expect("a value modulo a u64 fits in a u64")
+        }
+    }
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use common::grid;
+use piet_tool::{ArithmeticMode, BufferIo, PietError, PietProgram};
+
+// Red to yellow is add, and light red to dark yellow is multiply.
+const ADD: &[&str] = &["RY"];
+const MULTIPLY: &[&str] = &["LO"];
+
+// Runs the one step of `rows` with `a` and `b` on the stack, and returns the stack and
+// whether the step overflowed.
+fn run(rows: &[&str], mode: ArithmeticMode, a: i64, b: i64) -> Result<(Vec<i64>, bool), PietError> {
+    let mut program: PietProgram<BufferIo> = PietProgram::with_io(grid(rows), BufferIo::new(""));
+    program.set_arithmetic_mode(mode);
+    program.stack.push(a);
+    program.stack.push(b);
+    let outcome = program.step_once()?;
+    Ok((program.stack().values().to_vec(), outcome.overflowed))
+}
+
+#[test]
+fn wrapping_wraps_around() {
+    let mode = ArithmeticMode::Wrapping;
+    assert_eq!(run(ADD, mode, i64::MAX, 1).unwrap(), (vec![i64::MIN], true));
+    assert_eq!(run(MULTIPLY, mode, i64::MAX, 2).unwrap(), (vec![-2], true));
+    assert_eq!(run(ADD, mode, 1, 2).unwrap(), (vec![3], false));
+}
+
+#[test]
+fn saturating_clamps_to_the_bounds() {
+    let mode = ArithmeticMode::Saturating;
+    assert_eq!(run(ADD, mode, i64::MAX, 1).unwrap(), (vec![i64::MAX], true));
+    assert_eq!(
+        run(MULTIPLY, mode, i64::MAX, 2).unwrap(),
+        (vec![i64::MAX], true)
+    );
+    assert_eq!(
+        run(MULTIPLY, mode, i64::MIN, 2).unwrap(),
+        (vec![i64::MIN], true)
+    );
+}
+
+#[test]
+fn trapping_stops_at_the_codel() {
+    for rows in [ADD, MULTIPLY] {
+        match run(rows, ArithmeticMode::Trapping, i64::MAX, 2) {
+            Err(e @ PietError::Runtime { position, .. }) => {
+                assert_eq!(position, Some((1, 0)));
+                assert_eq!(e.exit_code(), 6);
+            }
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn promoting_needs_a_bignum_stack() {
+    // The stack holds i64s here, so there is nothing to promote to.
+    for rows in [ADD, MULTIPLY] {
+        assert!(matches!(
+            run(rows, ArithmeticMode::Promote, i64::MAX, 2),
+            Err(PietError::Runtime { .. })
+        ));
+    }
+}
+
+#[cfg(feature = "bigint")]
+#[test]
+fn promoting_goes_past_i64_max_on_a_bignum_stack() {
+    use num_bigint::BigInt;
+
+    // Adds, then prints the sum (yellow to dark red is out number).
+    for (mode, overflowed) in [
+        (ArithmeticMode::Promote, true),
+        (ArithmeticMode::Trapping, false),
+    ] {
+        let mut program: PietProgram<BufferIo, BigInt> =
+            PietProgram::with_io(grid(&["RYD"]), BufferIo::new(""));
+        program.set_arithmetic_mode(mode);
+        program.stack.push(BigInt::from(i64::MAX));
+        program.stack.push(BigInt::from(1));
+        assert_eq!(program.step_once().unwrap().overflowed, overflowed);
+        program.execute(2).unwrap();
+        assert_eq!(program.io().output_string(), "9223372036854775808");
+    }
+
+    let mut program: PietProgram<BufferIo, BigInt> =
+        PietProgram::with_io(grid(MULTIPLY), BufferIo::new(""));
+    program.set_arithmetic_mode(ArithmeticMode::Promote);
+    program.stack.push(BigInt::from(i64::MIN));
+    program.stack.push(BigInt::from(i64::MIN));
+    program.step_once().unwrap();
+    assert_eq!(
+        program.stack().values(),
+        ["85070591730234615865843651857942052864"
+            .parse::<BigInt>()
+            .unwrap()]
+    );
+}
+
+#[test]
+fn arithmetic_modes_parse() {
+    assert_eq!("wrapping".parse(), Ok(ArithmeticMode::Wrapping));
+    assert_eq!("trapping".parse(), Ok(ArithmeticMode::Trapping));
+    assert!("wrap".parse::<ArithmeticMode>().is_err());
+    assert!("".parse::<ArithmeticMode>().is_err());
+}
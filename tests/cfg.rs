@@ -0,0 +1,110 @@
+mod common;
+
+use common::grid;
+use piet_tool::cfg::{ControlFlowGraph, State};
+use piet_tool::color::ColorName;
+use piet_tool::translator::Translator;
+use piet_tool::{load_image, BufferIo, CodelChooser, Command, Direction, Grid, PietProgram};
+
+fn cfg(grid: &Grid) -> ControlFlowGraph {
+    let program: PietProgram<BufferIo> = PietProgram::with_io(grid.clone(), BufferIo::new(""));
+    ControlFlowGraph::build(grid, program.graph()).unwrap()
+}
+
+// Runs an example and checks that every step it takes is an edge of its static graph.
+fn assert_run_follows_cfg(example: &str, input: &str, max_steps: u64) {
+    let grid = load_image(&format!("examples/{}", example), 1).unwrap();
+    let cfg = cfg(&grid);
+    let mut program = PietProgram::<_, i64>::with_io(grid, BufferIo::new(input));
+    while !program.is_terminated() && program.steps() < max_steps {
+        let state = State {
+            block: program.current_block().id,
+            direction_pointer: program.direction_pointer(),
+            codel_chooser: program.codel_chooser(),
+        };
+        let starts_on_white =
+            program.steps() == 0 && program.current_block().color.name == ColorName::White;
+        let outcome = program.step_once().unwrap();
+        if starts_on_white {
+            continue;
+        }
+        let node = cfg
+            .find(&state)
+            .unwrap_or_else(|| panic!("{:?} is not in the graph", state));
+        let edge = match (&cfg.nodes()[node].edge, outcome.to) {
+            (Some(edge), Some(to)) => {
+                assert_eq!(edge.to.block, to.id);
+                edge
+            }
+            (None, None) => break,
+            (edge, to) => panic!("at step {}: {:?} vs {:?}", outcome.step, edge, to),
+        };
+        assert_eq!(edge.command, outcome.command);
+        let after = State {
+            block: program.current_block().id,
+            direction_pointer: program.direction_pointer(),
+            codel_chooser: program.codel_chooser(),
+        };
+        assert!(edge
+            .successors
+            .iter()
+            .any(|&successor| cfg.nodes()[successor].state == after));
+    }
+}
+
+#[test]
+fn runs_only_take_edges_of_the_graph() {
+    assert_run_follows_cfg("Piet_hello.png", "", 10_000);
+    assert_run_follows_cfg("Add.png", "3 4\n", 10_000);
+    assert_run_follows_cfg("cowsay.png", "hi\n", 10_000);
+    assert_run_follows_cfg("99bottles.png", "", 10_000);
+}
+
+#[test]
+fn pointer_and_switch_branch() {
+    // Red to dark cyan is a `Pointer`, which can leave the DP pointing anywhere.
+    let graph = cfg(&grid(&["RC"]));
+    let entry = &graph.nodes()[graph.entry().unwrap()];
+    let edge = entry.edge.as_ref().unwrap();
+    assert_eq!(edge.command, Some(Command::Pointer));
+    let pointers = edge
+        .successors
+        .iter()
+        .map(|&node| graph.nodes()[node].state.direction_pointer)
+        .collect::<Vec<Direction>>();
+    assert_eq!(pointers, Direction::ALL);
+
+    // Light red to dark cyan is a `Switch`.
+    let graph = cfg(&grid(&["LC"]));
+    let entry = &graph.nodes()[graph.entry().unwrap()];
+    let edge = entry.edge.as_ref().unwrap();
+    assert_eq!(edge.command, Some(Command::Switch));
+    let choosers = edge
+        .successors
+        .iter()
+        .map(|&node| graph.nodes()[node].state.codel_chooser)
+        .collect::<Vec<CodelChooser>>();
+    assert_eq!(choosers, [CodelChooser::Left, CodelChooser::Right]);
+}
+
+#[test]
+fn a_program_that_cannot_move_terminates_in_its_first_state() {
+    let graph = cfg(&grid(&["R"]));
+    assert_eq!(graph.nodes().len(), 1);
+    assert_eq!(graph.nodes()[0].edge, None);
+}
+
+#[test]
+fn translation_covers_every_reachable_state() {
+    let path = std::env::temp_dir().join("piet_tool_cfg.f");
+    let grid = load_image("examples/Piet_hello.png", 1).unwrap();
+    let states = cfg(&grid).nodes().len();
+    let mut translator = Translator::new(path.to_string_lossy().into_owned());
+    translator.translate(&grid).unwrap();
+    translator.flush().unwrap();
+    let forth = std::fs::read_to_string(&path).unwrap();
+    for node in 0..states {
+        assert!(forth.contains(&format!("\n    {} OF ", node)));
+    }
+    assert!(forth.trim_end().ends_with("PIET-RUN"));
+}
@@ -0,0 +1,29 @@
+use piet_tool::{Grid, PietColor};
+
+// Builds a grid from rows of single-letter color codes:
+// R = red, D = dark red, L = light red, Y = yellow, O = dark yellow, B = blue, C = dark cyan,
+// M = light magenta, W = white, K = black.
+pub fn grid(rows: &[&str]) -> Grid {
+    rows.iter()
+        .map(|row| {
+            row.chars()
+                .map(|c| {
+                    let rgb = match c {
+                        'R' => [255, 0, 0],
+                        'D' => [192, 0, 0],
+                        'L' => [255, 192, 192],
+                        'Y' => [255, 255, 0],
+                        'O' => [192, 192, 0],
+                        'B' => [0, 0, 255],
+                        'C' => [0, 192, 192],
+                        'M' => [255, 192, 255],
+                        'W' => [255, 255, 255],
+                        'K' => [0, 0, 0],
+                        _ => panic!("unknown color code {}", c),
+                    };
+                    PietColor::from_rgb(&rgb).unwrap()
+                })
+                .collect()
+        })
+        .collect()
+}
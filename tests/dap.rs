@@ -0,0 +1,311 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+// Hands bytes written on one thread to a reader on another, like a pipe.
+struct PipeReader {
+    chunks: Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.chunk.len() {
+            match self.chunks.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.position);
+        buf[..n].copy_from_slice(&self.chunk[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+struct PipeWriter(Sender<Vec<u8>>);
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn pipe() -> (PipeWriter, PipeReader) {
+    let (sender, receiver) = mpsc::channel();
+    let reader = PipeReader {
+        chunks: receiver,
+        chunk: Vec::new(),
+        position: 0,
+    };
+    (PipeWriter(sender), reader)
+}
+
+// Talks to a debug adapter running on another thread.
+struct Client {
+    requests: PipeWriter,
+    messages: Receiver<Value>,
+    // Events received while waiting for something else.
+    events: Vec<Value>,
+    seq: i64,
+}
+
+impl Client {
+    fn start() -> Self {
+        let (requests, server_input) = pipe();
+        let (server_output, responses) = pipe();
+        thread::spawn(move || piet_tool::dap::serve(server_input, server_output).unwrap());
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            let mut responses = BufReader::new(responses);
+            loop {
+                let mut header = String::new();
+                if responses.read_line(&mut header).unwrap() == 0 {
+                    return;
+                }
+                let length = header
+                    .trim()
+                    .strip_prefix("Content-Length: ")
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+                responses.read_line(&mut String::new()).unwrap();
+                let mut body = vec![0; length];
+                responses.read_exact(&mut body).unwrap();
+                if sender.send(serde_json::from_slice(&body).unwrap()).is_err() {
+                    return;
+                }
+            }
+        });
+        Client {
+            requests,
+            messages,
+            events: Vec::new(),
+            seq: 0,
+        }
+    }
+
+    fn receive(&mut self) -> Value {
+        self.messages
+            .recv_timeout(Duration::from_secs(10))
+            .expect("the debug adapter did not answer")
+    }
+
+    // Sends a request and returns its response.
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        self.seq += 1;
+        let body = json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+        .to_string();
+        write!(
+            self.requests,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .unwrap();
+        loop {
+            let message = self.receive();
+            if message["type"] == "response" {
+                assert_eq!(message["request_seq"], self.seq);
+                return message;
+            }
+            self.events.push(message);
+        }
+    }
+
+    // Waits for an event, returning its body.
+    fn event(&mut self, name: &str) -> Value {
+        if let Some(index) = self.events.iter().position(|event| event["event"] == name) {
+            return self.events.remove(index)["body"].take();
+        }
+        loop {
+            let message = self.receive();
+            if message["event"] == name {
+                return message["body"].clone();
+            }
+            self.events.push(message);
+        }
+    }
+
+    // All program output received so far.
+    fn output(&mut self) -> String {
+        let mut output = String::new();
+        self.events.retain(|event| {
+            let is_output = event["event"] == "output";
+            if is_output {
+                output.push_str(event["body"]["output"].as_str().unwrap());
+            }
+            !is_output
+        });
+        output
+    }
+
+    fn launch(&mut self, arguments: Value) {
+        let response = self.request("initialize", json!({ "adapterID": "piet" }));
+        assert_eq!(response["body"]["supportsConfigurationDoneRequest"], true);
+        let response = self.request("launch", arguments);
+        assert_eq!(response["success"], true, "{}", response);
+        self.event("initialized");
+    }
+
+    fn variables(&mut self, reference: i64) -> Vec<(String, String)> {
+        let response = self.request("variables", json!({ "variablesReference": reference }));
+        response["body"]["variables"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|variable| {
+                (
+                    variable["name"].as_str().unwrap().to_string(),
+                    variable["value"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect()
+    }
+}
+
+// Writes a one-row image of red (R) and dark red (D) codels.
+fn image(name: &str, row: &str) -> PathBuf {
+    let pixels = row
+        .chars()
+        .flat_map(|c| match c {
+            'R' => [255, 0, 0],
+            'D' => [192, 0, 0],
+            _ => panic!("unknown color code {}", c),
+        })
+        .collect::<Vec<u8>>();
+    let path = std::env::temp_dir().join(format!("piet_tool_dap_{}.png", name));
+    image::save_buffer(
+        &path,
+        &pixels,
+        row.len() as u32,
+        1,
+        image::ExtendedColorType::Rgb8,
+    )
+    .unwrap();
+    path
+}
+
+#[test]
+fn runs_to_function_breakpoints_and_to_the_end() {
+    let mut client = Client::start();
+    client.launch(json!({ "program": "examples/Piet_hello.png" }));
+    let response = client.request(
+        "setFunctionBreakpoints",
+        json!({ "breakpoints": [{ "name": "command out_char" }, { "name": "nonsense" }] }),
+    );
+    let breakpoints = &response["body"]["breakpoints"];
+    assert_eq!(breakpoints[0]["verified"], true);
+    assert_eq!(breakpoints[1]["verified"], false);
+    let id = breakpoints[0]["id"].clone();
+
+    client.request("configurationDone", json!({}));
+    let stopped = client.event("stopped");
+    assert_eq!(stopped["reason"], "breakpoint");
+    assert_eq!(stopped["hitBreakpointIds"], json!([id]));
+    assert_eq!(client.output(), "H");
+
+    let frames = client.request("stackTrace", json!({ "threadId": 1 }));
+    let frame = &frames["body"]["stackFrames"][0];
+    assert_eq!(frame["source"]["path"], "examples/Piet_hello.png");
+    let variables = client.variables(1);
+    let position = &variables
+        .iter()
+        .find(|(name, _)| name == "position")
+        .unwrap()
+        .1;
+    assert_eq!(
+        *position,
+        format!(
+            "({}, {})",
+            frame["column"].as_i64().unwrap() - 1,
+            frame["line"].as_i64().unwrap() - 1
+        )
+    );
+    assert!(variables.iter().any(|(name, _)| name == "DP"));
+    assert!(variables.iter().any(|(name, _)| name == "CC"));
+    assert!(variables.iter().any(|(name, _)| name == "block"));
+
+    client.request("continue", json!({ "threadId": 1 }));
+    assert_eq!(client.event("stopped")["reason"], "breakpoint");
+    assert_eq!(client.output(), "e");
+
+    client.request("setFunctionBreakpoints", json!({ "breakpoints": [] }));
+    client.request("continue", json!({ "threadId": 1 }));
+    client.event("terminated");
+    assert_eq!(client.output(), "llo world!");
+    client.request("disconnect", json!({}));
+}
+
+#[test]
+fn source_breakpoints_are_codels() {
+    // Red to dark red pushes 1, dark red back to red pops it, forever.
+    let path = image("loop", "RDD");
+    let mut client = Client::start();
+    client.launch(json!({ "program": path, "stopOnEntry": true }));
+    // Line 1, column 3 is codel (2, 0), in the dark red block.
+    let response = client.request(
+        "setBreakpoints",
+        json!({
+            "source": { "path": path },
+            "breakpoints": [{ "line": 1, "column": 3 }, { "line": 5, "column": 1 }],
+        }),
+    );
+    let breakpoints = &response["body"]["breakpoints"];
+    assert_eq!(breakpoints[0]["verified"], true);
+    assert_eq!(breakpoints[1]["verified"], false);
+    client.request("configurationDone", json!({}));
+    assert_eq!(client.event("stopped")["reason"], "entry");
+
+    client.request("continue", json!({ "threadId": 1 }));
+    assert_eq!(client.event("stopped")["reason"], "breakpoint");
+    assert_eq!(
+        client.variables(2),
+        vec![("0".to_string(), "1".to_string())]
+    );
+
+    client.request("next", json!({ "threadId": 1 }));
+    assert_eq!(client.event("stopped")["reason"], "step");
+    assert!(client.variables(2).is_empty());
+    client.request("stepBack", json!({ "threadId": 1 }));
+    assert_eq!(client.event("stopped")["reason"], "step");
+    assert_eq!(client.variables(2).len(), 1);
+    client.request("disconnect", json!({}));
+}
+
+#[test]
+fn pause_stops_a_running_program() {
+    let path = image("pause", "RD");
+    let mut client = Client::start();
+    client.launch(json!({ "program": path }));
+    client.request("configurationDone", json!({}));
+    thread::sleep(Duration::from_millis(50));
+    client.request("pause", json!({ "threadId": 1 }));
+    assert_eq!(client.event("stopped")["reason"], "pause");
+    let step = client.variables(1)[0].1.parse::<i32>().unwrap();
+    assert!(step > 0);
+    // Nothing runs while paused.
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(client.variables(1)[0].1.parse::<i32>().unwrap(), step);
+    client.request("disconnect", json!({}));
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use common::grid;
+use piet_tool::{Breakpoint, BufferIo, Command, Debugger, PietProgram, StopReason};
+
+// Pushes 1 going from red into the dark red block, then slides off through white
+// and terminates.
+fn program() -> PietProgram<BufferIo> {
+    PietProgram::with_io(grid(&["RDDW", "KKKW"]), BufferIo::new(""))
+}
+
+fn debugger() -> Debugger<BufferIo> {
+    Debugger::new(program())
+}
+
+#[test]
+fn step_executes_the_given_number_of_steps() {
+    let mut debugger = debugger();
+    assert_eq!(debugger.step(1).unwrap(), StopReason::Stepped);
+    assert_eq!(debugger.program().steps(), 1);
+    assert_eq!(debugger.program().stack().values(), &[1]);
+}
+
+#[test]
+fn continue_runs_to_termination_without_breakpoints() {
+    let mut debugger = debugger();
+    assert_eq!(debugger.cont().unwrap(), StopReason::Terminated);
+    assert!(debugger.program().is_terminated());
+}
+
+#[test]
+fn codel_breakpoint_stops_on_that_codel_only() {
+    let mut debugger = debugger();
+    let program = debugger.program_mut();
+    program.add_breakpoint(Breakpoint::Codel((2, 0))).unwrap();
+    let entry = program.add_breakpoint(Breakpoint::Codel((1, 0))).unwrap();
+    assert_eq!(debugger.cont().unwrap(), StopReason::Breakpoint(entry));
+    assert_eq!(debugger.program().position(), (1, 0));
+}
+
+#[test]
+fn block_breakpoint_stops_anywhere_in_the_block() {
+    let mut debugger = debugger();
+    let number = debugger
+        .program_mut()
+        .add_breakpoint(Breakpoint::Block((2, 0)))
+        .unwrap();
+    assert_eq!(debugger.cont().unwrap(), StopReason::Breakpoint(number));
+    assert_eq!(debugger.program().current_block().position, (1, 0));
+}
+
+#[test]
+fn removed_breakpoint_no_longer_stops() {
+    let mut debugger = debugger();
+    let program = debugger.program_mut();
+    let number = program.add_breakpoint(Breakpoint::Codel((1, 0))).unwrap();
+    assert_eq!(
+        program.remove_breakpoint(number),
+        Some(Breakpoint::Codel((1, 0)))
+    );
+    assert_eq!(debugger.cont().unwrap(), StopReason::Terminated);
+}
+
+#[test]
+fn repl_runs_commands() {
+    let mut debugger = debugger();
+    let script = "break 1,0\ncontinue\nprint stack\nprint dp\nwhere\nquit\n";
+    let mut output = Vec::new();
+    debugger.repl(script.as_bytes(), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("Breakpoint 1: 1,0"));
+    assert!(output.contains("Breakpoint 1 hit."));
+    assert!(output.contains("Stack (len 1, top first): 1"));
+    assert!(output.contains("DP: Right"));
+    assert!(output.contains("Exit codel (2, 0) leads to (3, 0)"));
+}
+
+#[test]
+fn breakpoint_outside_the_program_is_rejected() {
+    let mut debugger = debugger();
+    assert!(debugger
+        .program_mut()
+        .add_breakpoint(Breakpoint::Block((4, 0)))
+        .is_err());
+}
+
+#[test]
+fn breakpoints_parse_from_text() {
+    let parse = |text: &str| text.parse::<Breakpoint>().unwrap();
+    assert_eq!(parse("3, 4"), Breakpoint::Codel((3, 4)));
+    assert_eq!(parse("block 3,4"), Breakpoint::Block((3, 4)));
+    assert_eq!(
+        parse("command out_char"),
+        Breakpoint::Command(Command::OutChar)
+    );
+    assert_eq!(parse("command Roll"), Breakpoint::Command(Command::Roll));
+    assert_eq!(parse("depth > 10"), Breakpoint::DepthAbove(10));
+    assert_eq!(parse("depth<2"), Breakpoint::DepthBelow(2));
+    assert_eq!(parse("top=-5"), Breakpoint::TopEquals(-5));
+    assert_eq!(parse("pointer"), Breakpoint::PointerChange);
+    assert!("command jump".parse::<Breakpoint>().is_err());
+    assert!("depth".parse::<Breakpoint>().is_err());
+}
+
+#[test]
+fn command_breakpoint_stops_after_the_command() {
+    let mut debugger = debugger();
+    let number = debugger
+        .program_mut()
+        .add_breakpoint(Breakpoint::Command(Command::Push))
+        .unwrap();
+    assert_eq!(debugger.cont().unwrap(), StopReason::Breakpoint(number));
+    assert_eq!(debugger.program().stack().values(), &[1]);
+}
+
+#[test]
+fn stack_breakpoints_stop_in_the_run_loop() {
+    for breakpoint in [Breakpoint::DepthAbove(0), Breakpoint::TopEquals(1)] {
+        let mut program = program();
+        let number = program.add_breakpoint(breakpoint).unwrap();
+        assert_eq!(program.execute(-1).unwrap(), StopReason::Breakpoint(number));
+        assert_eq!(program.steps(), 1);
+        assert!(!program.is_terminated());
+    }
+}
+
+#[test]
+fn stack_breakpoints_that_never_match_do_not_stop() {
+    for breakpoint in [Breakpoint::DepthBelow(1), Breakpoint::TopEquals(2)] {
+        let mut program = program();
+        program.add_breakpoint(breakpoint).unwrap();
+        assert_eq!(program.execute(-1).unwrap(), StopReason::Terminated);
+    }
+}
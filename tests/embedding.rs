@@ -0,0 +1,38 @@
+use piet_tool::stack::Stack;
+use piet_tool::{load_image, load_image_from_bytes, PietProgram};
+
+#[test]
+fn images_load_the_same_from_disk_and_memory() {
+    let bytes = std::fs::read("examples/Piet_hello.png").unwrap();
+    let grid = load_image("examples/Piet_hello.png", 1).unwrap();
+    assert_eq!(load_image_from_bytes(&bytes, 1).unwrap(), grid);
+    assert!(!grid.is_empty());
+    assert!(grid.iter().all(|row| row.len() == grid[0].len()));
+}
+
+#[test]
+fn programs_run_until_they_terminate() {
+    let mut program = PietProgram::new(load_image("examples/Piet_hello.png", 1).unwrap());
+    program.execute(10_000).unwrap();
+    assert!(program.is_terminated());
+    assert!(program.steps() > 0 && program.steps() < 10_000);
+}
+
+#[test]
+fn programs_stop_at_the_step_limit() {
+    let mut program = PietProgram::new(load_image("examples/Endless.png", 1).unwrap());
+    program.execute(50).unwrap();
+    assert!(!program.is_terminated());
+    assert_eq!(program.steps(), 50);
+}
+
+#[test]
+fn roll_rotates_the_top_of_the_stack() {
+    let mut stack = Stack::new();
+    for value in [1, 2, 3, 3, 1] {
+        stack.push(value);
+    }
+    stack.roll();
+    assert_eq!(stack.values(), [3, 1, 2]);
+    assert_eq!(stack.peek(), Some(&2));
+}
@@ -0,0 +1,60 @@
+use piet_tool::{load_image, load_image_from_bytes, PietError};
+
+#[test]
+fn invalid_colors_are_reported_with_their_codel() {
+    match load_image("examples/ColorError.png", 1) {
+        Err(error @ PietError::Color { .. }) => {
+            assert_eq!(error.exit_code(), 3);
+            assert_eq!(
+                error.to_string(),
+                "invalid color [157, 63, 63] at codel (15, 3)"
+            );
+            assert!(matches!(
+                error,
+                PietError::Color {
+                    x: 15,
+                    y: 3,
+                    rgb: [157, 63, 63]
+                }
+            ));
+        }
+        other => panic!("expected a color error, got {:?}", other),
+    }
+
+    // The same codel in an image twice the size, loaded with a codel size of 2.
+    let image = image::open("examples/ColorError.png").unwrap();
+    let (width, height) = (image.width() * 2, image.height() * 2);
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    image
+        .resize(width, height, image::imageops::FilterType::Nearest)
+        .write_to(&mut bytes, image::ImageFormat::Png)
+        .unwrap();
+    assert!(matches!(
+        load_image_from_bytes(bytes.get_ref(), 2),
+        Err(PietError::Color { x: 15, y: 3, .. })
+    ));
+}
+
+#[test]
+fn runtime_errors_take_the_first_position_they_are_given() {
+    let error = PietError::runtime("oops").at((1, 2));
+    assert!(matches!(
+        error,
+        PietError::Runtime {
+            position: Some((1, 2)),
+            ..
+        }
+    ));
+    assert!(matches!(
+        error.at((3, 4)),
+        PietError::Runtime {
+            position: Some((1, 2)),
+            ..
+        }
+    ));
+
+    // Other kinds of error have no position to fill in.
+    let error = PietError::Translation("bad".to_string()).at((1, 2));
+    assert!(matches!(error, PietError::Translation(_)));
+    assert_eq!(error.exit_code(), 5);
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use common::grid;
+use piet_tool::graph::{BlockGraph, Exit};
+use piet_tool::{CodelChooser, Direction};
+
+// A red block shaped so that each DP/CC combination leaves it from a different codel,
+// with something different beyond each of them.
+const ROWS: &[&str] = &[
+    "KYBKW", //
+    "KRRKW", //
+    "RRRRC", //
+    "RRRRK", //
+    "WRRWW", //
+    "WWDWW",
+];
+
+#[test]
+fn blocks_are_labelled_in_reading_order() {
+    let graph = BlockGraph::compile(&grid(ROWS));
+    let sizes = graph
+        .blocks()
+        .iter()
+        .map(|block| (block.codels[0], block.size()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sizes,
+        [
+            ((0, 0), 2),
+            ((1, 0), 1),
+            ((2, 0), 1),
+            ((3, 0), 2),
+            ((4, 0), 2),
+            ((1, 1), 12),
+            ((4, 2), 1),
+            ((4, 3), 1),
+            ((0, 4), 3),
+            ((3, 4), 4),
+            ((2, 5), 1),
+        ]
+    );
+    for (id, block) in graph.blocks().iter().enumerate() {
+        assert_eq!(block.id, id);
+        for &codel in &block.codels {
+            assert_eq!(graph.block_at(codel).id, id);
+        }
+    }
+}
+
+#[test]
+fn every_dp_and_cc_has_its_own_exit() {
+    let graph = BlockGraph::compile(&grid(ROWS));
+    let red = graph.block_at((2, 2));
+    let exit = |codel, next: Option<(i32, i32)>| Exit {
+        codel,
+        next,
+        target: next.map(|next| graph.block_at(next).id),
+    };
+    let expected = [
+        (
+            Direction::Right,
+            CodelChooser::Left,
+            exit((3, 2), Some((4, 2))),
+        ),
+        (Direction::Right, CodelChooser::Right, exit((3, 3), None)),
+        (
+            Direction::Down,
+            CodelChooser::Left,
+            exit((2, 4), Some((2, 5))),
+        ),
+        (
+            Direction::Down,
+            CodelChooser::Right,
+            exit((1, 4), Some((1, 5))),
+        ),
+        (Direction::Left, CodelChooser::Left, exit((0, 3), None)),
+        (Direction::Left, CodelChooser::Right, exit((0, 2), None)),
+        (
+            Direction::Up,
+            CodelChooser::Left,
+            exit((1, 1), Some((1, 0))),
+        ),
+        (
+            Direction::Up,
+            CodelChooser::Right,
+            exit((2, 1), Some((2, 0))),
+        ),
+    ];
+    for (dp, cc, exit) in expected {
+        assert_eq!(*red.exit(dp, cc), exit, "{:?} {:?}", dp, cc);
+    }
+    assert_eq!(
+        red.exit(Direction::Down, CodelChooser::Right).target,
+        Some(8)
+    );
+    assert_eq!(red.exit(Direction::Up, CodelChooser::Left).target, Some(1));
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use common::grid;
+use piet_tool::interpreter::DEFAULT_HISTORY_LIMIT;
+use piet_tool::{
+    load_image, Breakpoint, BufferIo, CodelChooser, Command, Debugger, Direction, PietProgram,
+    StopReason,
+};
+
+type State = (
+    Vec<i64>,
+    Direction,
+    CodelChooser,
+    (i32, i32),
+    u64,
+    usize,
+    usize,
+);
+
+fn program(path: &str, input: &str) -> PietProgram<BufferIo> {
+    let mut program = PietProgram::with_io(load_image(path, 1).unwrap(), BufferIo::new(input));
+    program.set_history(true);
+    program
+}
+
+fn state(program: &PietProgram<BufferIo>) -> State {
+    (
+        program.stack().values().to_vec(),
+        program.direction_pointer(),
+        program.codel_chooser(),
+        program.position(),
+        program.steps(),
+        program.input_position(),
+        program.output_position(),
+    )
+}
+
+#[test]
+fn stepping_back_retraces_every_step() {
+    let mut program = program("examples/99bottles.png", "");
+    let mut states = vec![state(&program)];
+    for _ in 0..3000 {
+        program.step_once().unwrap();
+        states.push(state(&program));
+    }
+    assert_eq!(program.history_len(), 3000);
+    states.pop();
+    while let Some(expected) = states.pop() {
+        assert!(program.step_back());
+        assert_eq!(state(&program), expected);
+    }
+    assert!(!program.step_back());
+}
+
+#[test]
+fn limited_history_keeps_the_most_recent_steps() {
+    let mut program = program("examples/99bottles.png", "");
+    program.set_history_limit(100);
+    assert_eq!(program.history_limit(), Some(100));
+    let mut states = vec![state(&program)];
+    for _ in 0..300 {
+        program.step_once().unwrap();
+        states.push(state(&program));
+    }
+    assert_eq!(program.history_len(), 100);
+    states.pop();
+    for expected in states.iter().rev().take(100) {
+        assert!(program.step_back());
+        assert_eq!(&state(&program), expected);
+    }
+    assert!(!program.step_back());
+    assert_eq!(program.steps(), 200);
+}
+
+#[test]
+fn debugger_limits_its_history() {
+    let debugger = Debugger::new(program("examples/Piet_hello.png", ""));
+    assert_eq!(
+        debugger.program().history_limit(),
+        Some(DEFAULT_HISTORY_LIMIT)
+    );
+}
+
+#[test]
+fn input_is_read_again_after_stepping_back() {
+    let mut program = program("examples/Add.png", "3 4");
+    program.execute(-1).unwrap();
+    let output = program.io().output().to_vec();
+    while program.step_back() {}
+    assert_eq!(program.input_position(), 0);
+    assert_eq!(program.output_position(), 0);
+    program.execute(-1).unwrap();
+    assert_eq!(program.io().output(), [output.clone(), output].concat());
+}
+
+#[test]
+fn debugger_runs_back_to_breakpoints_and_output() {
+    let mut debugger = Debugger::new(program("examples/Piet_hello.png", ""));
+    let number = debugger
+        .program_mut()
+        .add_breakpoint(Breakpoint::Command(Command::OutChar))
+        .unwrap();
+    assert_eq!(debugger.cont().unwrap(), StopReason::Breakpoint(number));
+    let first = debugger.program().steps();
+    assert_eq!(debugger.cont().unwrap(), StopReason::Breakpoint(number));
+    assert_eq!(debugger.reverse_cont(), StopReason::Breakpoint(number));
+    assert_eq!(debugger.program().steps(), first);
+    assert_eq!(debugger.reverse_cont(), StopReason::HistoryStart);
+
+    debugger.program_mut().remove_breakpoint(number);
+    debugger.step(20).unwrap();
+    assert_eq!(debugger.back_to_output(3), StopReason::Stepped);
+    assert_eq!(debugger.program().output_position(), 3);
+    debugger.step(1).unwrap();
+    assert_eq!(debugger.program().output_position(), 4);
+}
+
+#[test]
+fn failed_step_can_be_undone() {
+    // Red to yellow is add, which overflows with the maximum value on the stack.
+    let mut program: PietProgram<BufferIo> = PietProgram::with_io(grid(&["RY"]), BufferIo::new(""));
+    program.set_history(true);
+    program.stack.push(i64::MAX);
+    program.stack.push(1);
+    assert!(program.step_once().is_err());
+    assert!(program.step_back());
+    assert_eq!(program.steps(), 0);
+    assert_eq!(program.stack().values(), &[i64::MAX, 1]);
+}
+
+#[test]
+fn reverse_continue_stops_at_breakpoints_set_afterwards() {
+    let mut debugger = Debugger::new(program("examples/Piet_hello.png", ""));
+    assert_eq!(debugger.cont().unwrap(), StopReason::Terminated);
+    let written = debugger.program().output_position();
+
+    // Going back from the end, the last character written is the first stop.
+    let out_char = debugger
+        .program_mut()
+        .add_breakpoint(Breakpoint::Command(Command::OutChar))
+        .unwrap();
+    assert_eq!(debugger.reverse_cont(), StopReason::Breakpoint(out_char));
+    assert_eq!(debugger.program().output_position(), written);
+
+    // A block breakpoint set now on the block the program is in is hit on
+    // the way back as well, even after the command breakpoint that was hit there is gone.
+    let position = debugger.program().position();
+    let block = debugger
+        .program_mut()
+        .add_breakpoint(Breakpoint::Block(position))
+        .unwrap();
+    debugger.program_mut().remove_breakpoint(out_char);
+    let steps = debugger.program().steps();
+    debugger.step(1).unwrap();
+    assert_eq!(debugger.reverse_cont(), StopReason::Breakpoint(block));
+    assert_eq!(debugger.program().steps(), steps);
+}
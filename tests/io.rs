@@ -0,0 +1,128 @@
+mod common;
+
+use std::io::{Cursor, Read};
+
+use common::grid;
+use piet_tool::io::StreamIo;
+use piet_tool::{BufferIo, FileIo, PietIo, PietProgram};
+
+fn program(input: impl Into<Vec<u8>>) -> PietProgram<BufferIo> {
+    PietProgram::with_io(grid(&["R"]), BufferIo::new(input))
+}
+
+#[test]
+fn numbers_leave_the_next_character_in_the_input() {
+    let mut program = program("12a -7\n5\u{e9}");
+    assert_eq!(program.read_number().unwrap(), Some(12));
+    assert_eq!(program.read_char().unwrap(), Some('a' as i64));
+    assert_eq!(program.read_number().unwrap(), Some(-7));
+    assert_eq!(program.read_char().unwrap(), Some('\n' as i64));
+    // The first byte of the character after the number was read ahead, the second wasn't.
+    assert_eq!(program.read_number().unwrap(), Some(5));
+    assert_eq!(program.io().remaining_input(), [0xA9]);
+    assert_eq!(program.input_position(), 8);
+    assert_eq!(program.read_char().unwrap(), Some(0xE9));
+    assert_eq!(program.input_position(), 10);
+    assert_eq!(program.read_char().unwrap(), None);
+}
+
+#[test]
+fn input_that_is_not_a_number_is_skipped() {
+    let mut program = program("x 42");
+    assert_eq!(program.read_number().unwrap(), None);
+    assert_eq!(program.read_number().unwrap(), Some(42));
+}
+
+#[test]
+fn numbers_can_end_the_input() {
+    let mut program = program("  42");
+    assert_eq!(program.read_number().unwrap(), Some(42));
+    assert_eq!(program.read_number().unwrap(), None);
+    assert_eq!(program.read_char().unwrap(), None);
+
+    let mut program = self::program("");
+    assert_eq!(program.read_number().unwrap(), None);
+    assert_eq!(program.read_char().unwrap(), None);
+    assert_eq!(program.input_position(), 0);
+}
+
+#[test]
+fn characters_are_read_as_utf8() {
+    let mut program = program("\u{e9}\u{20ac}\u{1f600}z");
+    assert_eq!(program.read_char().unwrap(), Some(0xE9));
+    assert_eq!(program.read_char().unwrap(), Some(0x20AC));
+    assert_eq!(program.read_char().unwrap(), Some(0x1F600));
+    assert_eq!(program.read_char().unwrap(), Some('z' as i64));
+
+    // Bytes that aren't UTF-8 are read as themselves.
+    let mut program = self::program([0xFF, 0x41]);
+    assert_eq!(program.read_char().unwrap(), Some(0xFF));
+    assert_eq!(program.read_char().unwrap(), Some(0x41));
+    let mut program = self::program([0xE2, 0x82]);
+    assert_eq!(program.read_char().unwrap(), Some(0xE2));
+    assert_eq!(program.read_char().unwrap(), None);
+}
+
+#[test]
+fn characters_are_written_as_utf8() {
+    let mut program = program("");
+    for c in ['a', '\u{e9}', '\u{20ac}', '\u{1f600}'] {
+        program.write_char(&(c as i64)).unwrap();
+    }
+    // Values that aren't characters are left out.
+    for value in [-1, 0xD800, 0x110000] {
+        program.write_char(&value).unwrap();
+    }
+    assert_eq!(program.io().output_string(), "a\u{e9}\u{20ac}\u{1f600}");
+    assert_eq!(program.output_position(), 10);
+}
+
+#[test]
+fn buffer_io_reads_its_input_and_collects_output() {
+    let mut io = BufferIo::new("ab");
+    assert_eq!(io.read_byte().unwrap(), Some(b'a'));
+    assert_eq!(io.remaining_input(), b"b");
+    assert_eq!(io.read_byte().unwrap(), Some(b'b'));
+    assert_eq!(io.read_byte().unwrap(), None);
+    io.write_bytes(b"x").unwrap();
+    io.write_bytes(&[0xC3, 0xA9, 0xFF]).unwrap();
+    io.flush().unwrap();
+    assert_eq!(io.output(), [b'x', 0xC3, 0xA9, 0xFF]);
+    assert_eq!(io.output_string(), "x\u{e9}\u{fffd}");
+}
+
+#[test]
+fn stream_io_reads_and_writes_its_streams() {
+    let mut io = StreamIo::new(Cursor::new(b"hi".to_vec()), Vec::new());
+    assert_eq!(io.read_byte().unwrap(), Some(b'h'));
+    assert_eq!(io.read_byte().unwrap(), Some(b'i'));
+    assert_eq!(io.read_byte().unwrap(), None);
+    io.write_bytes(b"out").unwrap();
+    let (reader, writer) = io.into_inner();
+    assert_eq!(reader.position(), 2);
+    assert_eq!(writer, b"out");
+}
+
+#[test]
+fn file_io_runs_a_program_between_files() {
+    let directory = std::env::temp_dir();
+    let input = directory.join(format!("piet_tool_io_{}.in", std::process::id()));
+    let output = directory.join(format!("piet_tool_io_{}.out", std::process::id()));
+    std::fs::write(&input, "moo\n").unwrap();
+    let grid = piet_tool::load_image("examples/cowsay.png", 1).unwrap();
+    let mut expected: PietProgram<BufferIo> =
+        PietProgram::with_io(grid.clone(), BufferIo::new("moo\n"));
+    expected.execute(-1).unwrap();
+
+    let mut program: PietProgram<FileIo> =
+        PietProgram::with_io(grid, FileIo::open(&input, &output).unwrap());
+    program.execute(-1).unwrap();
+    // `execute` flushes the output when it stops.
+    let mut written = Vec::new();
+    std::fs::File::open(&output)
+        .unwrap()
+        .read_to_end(&mut written)
+        .unwrap();
+    assert!(written.windows(3).any(|window| window == b"moo"));
+    assert_eq!(written, expected.io().output());
+}
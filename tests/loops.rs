@@ -0,0 +1,47 @@
+mod common;
+
+use common::grid;
+use piet_tool::{load_image, BufferIo, PietError, PietProgram};
+
+fn program(grid: piet_tool::Grid) -> PietProgram<BufferIo> {
+    let mut program = PietProgram::with_io(grid, BufferIo::new(""));
+    program.set_loop_detection(true);
+    program
+}
+
+#[test]
+fn endless_example_is_caught_looping() {
+    let mut program = program(load_image("examples/Endless.png", 1).unwrap());
+    let error = program.execute(-1).unwrap_err();
+    assert_eq!(error.exit_code(), 7);
+    match error {
+        PietError::InfiniteLoop {
+            step,
+            cycle_length,
+            blocks,
+        } => {
+            assert_eq!(step, 5);
+            assert_eq!(cycle_length, 4);
+            assert_eq!(blocks, vec![(1, 0), (2, 0), (0, 0)]);
+        }
+        other => panic!("expected an infinite loop, got {:?}", other),
+    }
+}
+
+#[test]
+fn repeated_states_with_output_in_between_are_not_a_loop() {
+    // Pushes 1, prints it and heads back, so it passes through the same states over and
+    // over, but writes output every time round.
+    let mut program = program(grid(&["RDMW"]));
+    program.execute(200).unwrap();
+    assert_eq!(program.steps(), 200);
+    assert!(program.io().output_string().starts_with("111"));
+    assert!(program.stack().len() <= 1);
+
+    // The same program without the output is caught.
+    let mut program = self::program(grid(&["RDRDW"]));
+    assert!(matches!(
+        program.execute(200),
+        Err(PietError::InfiniteLoop { .. })
+    ));
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use common::grid;
+use image::codecs::gif::GifDecoder;
+use image::AnimationDecoder;
+use piet_tool::render::{draw_heatmap, draw_state, render_gif, GifOptions};
+use piet_tool::{load_image, BufferIo, PietError, PietProgram};
+
+fn program(rows: &[&str]) -> PietProgram<BufferIo> {
+    PietProgram::with_io(grid(rows), BufferIo::new(""))
+}
+
+#[test]
+fn state_is_drawn_at_scale() {
+    let program = program(&["RDDW", "KKKW"]);
+    let image = draw_state(&program, 10, false);
+    assert_eq!(image.dimensions(), (40, 20));
+    // The current block is outlined in white, which stands out against red.
+    assert_eq!(image.get_pixel(0, 0).0, [255, 255, 255, 255]);
+    assert_eq!(image.get_pixel(15, 5).0, [192, 0, 0, 255]);
+
+    let image = draw_state(&program, 10, true);
+    assert!(image.width() > 40);
+    assert!(image.height() >= 20);
+}
+
+#[test]
+fn gif_has_a_frame_per_drawn_step() {
+    // "RDDW", "KKKW" terminates after 2 steps, so it has 3 states.
+    let mut output = Vec::new();
+    let frames = render_gif(
+        &mut program(&["RDDW", "KKKW"]),
+        &GifOptions::default(),
+        &mut output,
+    )
+    .unwrap();
+    assert_eq!(frames, 3);
+    let decoded = GifDecoder::new(std::io::Cursor::new(output))
+        .unwrap()
+        .into_frames()
+        .collect_frames()
+        .unwrap();
+    assert_eq!(decoded.len(), 3);
+    assert_eq!(decoded[0].buffer().dimensions(), (40, 20));
+
+    // Every other step of the first 5 of a program that never stops.
+    let options = GifOptions {
+        every: 2,
+        to: 4,
+        scale: 3,
+        ..GifOptions::default()
+    };
+    let frames = render_gif(&mut program(&["RDRDW"]), &options, &mut Vec::new()).unwrap();
+    assert_eq!(frames, 3);
+}
+
+#[test]
+fn gif_of_a_program_that_never_stops_needs_a_last_step() {
+    let grid = load_image("examples/Endless.png", 1).unwrap();
+    let options = GifOptions {
+        scale: 1,
+        max_steps: 20,
+        ..GifOptions::default()
+    };
+    let mut endless: PietProgram<BufferIo> = PietProgram::with_io(grid.clone(), BufferIo::new(""));
+    match render_gif(&mut endless, &options, &mut Vec::new()) {
+        Err(error @ PietError::Runtime { .. }) => {
+            assert!(error.to_string().contains("20 steps"), "{}", error)
+        }
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+    assert_eq!(endless.steps(), 20);
+
+    // A last step lifts the limit.
+    let options = GifOptions { to: 30, ..options };
+    let mut endless: PietProgram<BufferIo> = PietProgram::with_io(grid, BufferIo::new(""));
+    assert_eq!(
+        render_gif(&mut endless, &options, &mut Vec::new()).unwrap(),
+        31
+    );
+}
+
+#[test]
+fn profile_counts_entries_and_moves() {
+    // Goes right to the white codel, slides back into the last block and heads left.
+    let mut program = program(&["RDRDW"]);
+    program.set_profiling(true);
+    program.execute(6).unwrap();
+    let profile = program.profile().unwrap();
+    let entries = (0..5)
+        .map(|x| profile.entries(program.graph().block_at((x, 0)).id))
+        .collect::<Vec<u64>>();
+    assert_eq!(entries, [1, 2, 2, 2, 0]);
+    let transitions = profile.transitions();
+    assert_eq!(transitions.len(), 6);
+    assert!(transitions.iter().all(|&(_, count)| count == 1));
+
+    let image = draw_heatmap(&program, 4);
+    assert_eq!(image.dimensions(), (20, 4));
+    // The white codel was never entered and is greyed out.
+    assert_eq!(image.get_pixel(17, 1).0, [112, 112, 112, 255]);
+}
@@ -0,0 +1,87 @@
+use piet_tool::snapshot::SNAPSHOT_VERSION;
+use piet_tool::{load_image, BufferIo, PietError, PietProgram, Snapshot};
+
+fn program(example: &str, input: &str) -> PietProgram<BufferIo> {
+    let grid = load_image(&format!("examples/{}", example), 1).unwrap();
+    PietProgram::with_io(grid, BufferIo::new(input))
+}
+
+fn assert_snapshot_error<T: std::fmt::Debug>(result: piet_tool::Result<T>, message: &str) {
+    match result {
+        Err(PietError::Snapshot(error)) => assert!(error.contains(message), "{}", error),
+        other => panic!("expected a snapshot error, got {:?}", other),
+    }
+}
+
+#[test]
+fn snapshots_survive_a_round_trip_through_json() {
+    let mut program = program("Add.png", "3 4");
+    program.execute(5).unwrap();
+    let snapshot = program.snapshot();
+    let restored = Snapshot::from_json(&snapshot.to_json()).unwrap();
+    assert_eq!(restored, snapshot);
+
+    let mut resumed = self::program("Add.png", "");
+    resumed.restore(&restored).unwrap();
+    assert_eq!(resumed.snapshot(), snapshot);
+    assert_eq!(resumed.stack().values(), program.stack().values());
+    assert_eq!(resumed.position(), program.position());
+    assert_eq!(resumed.direction_pointer(), program.direction_pointer());
+    assert_eq!(resumed.codel_chooser(), program.codel_chooser());
+    assert_eq!(resumed.steps(), program.steps());
+}
+
+#[test]
+fn resuming_gives_the_same_output_as_an_uninterrupted_run() {
+    for (example, input) in [("Add.png", "3 4"), ("Piet_hello.png", "")] {
+        let mut uninterrupted = program(example, input);
+        uninterrupted.execute(-1).unwrap();
+        let expected = uninterrupted.io().output_string();
+
+        for stop in 0..uninterrupted.steps() {
+            let mut first = program(example, input);
+            first.execute(stop as i64).unwrap();
+            let snapshot = Snapshot::from_json(&first.snapshot().to_json()).unwrap();
+
+            // The resumed run gets the input the first run hasn't read yet, as `--resume`
+            // does with an input file.
+            let mut second = program(example, &input[snapshot.input_read..]);
+            second.restore(&snapshot).unwrap();
+            second.execute(-1).unwrap();
+            assert_eq!(second.steps(), uninterrupted.steps());
+            assert_eq!(
+                first.io().output_string() + &second.io().output_string(),
+                expected,
+                "{} resumed after {} steps",
+                example,
+                stop
+            );
+        }
+    }
+}
+
+#[test]
+fn snapshots_of_other_images_are_rejected() {
+    let snapshot = program("Add.png", "").snapshot();
+    let mut other = program("Piet_hello.png", "");
+    assert_snapshot_error(other.restore(&snapshot), "different image");
+}
+
+#[test]
+fn positions_outside_the_program_are_rejected() {
+    let mut program = program("Add.png", "");
+    let (width, height) = (program.grid()[0].len() as i32, program.grid().len() as i32);
+    for position in [(-1, 0), (0, -1), (width, 0), (0, height)] {
+        let mut snapshot = program.snapshot();
+        snapshot.position = position;
+        assert_snapshot_error(program.restore(&snapshot), "outside the program");
+    }
+}
+
+#[test]
+fn unknown_versions_are_rejected() {
+    let mut json: serde_json::Value =
+        serde_json::from_str(&program("Add.png", "").snapshot().to_json()).unwrap();
+    json["version"] = (SNAPSHOT_VERSION + 1).into();
+    assert_snapshot_error(Snapshot::from_json(&json.to_string()), "version");
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::grid;
+use piet_tool::command::Command;
+use piet_tool::interpreter::Recovery;
+use piet_tool::{BufferIo, CodelChooser, Direction, PietProgram};
+
+fn program(rows: &[&str]) -> PietProgram<BufferIo> {
+    PietProgram::with_io(grid(rows), BufferIo::new(""))
+}
+
+#[test]
+fn blocked_exits_are_recovered_from_in_order() {
+    let mut program = program(&["RDK", "KKK"]);
+    let outcome = program.step_once().unwrap();
+    assert_eq!(outcome.command, Some(Command::Push));
+    assert!(!outcome.was_blocked());
+
+    // The dark red block is walled in to the right and below, so the interpreter tries the
+    // other CC, then turns the DP clockwise, until it can leave to the left.
+    let outcome = program.step_once().unwrap();
+    assert_eq!(outcome.step, 1);
+    assert_eq!(outcome.from.position, (1, 0));
+    assert_eq!(outcome.from.size, 1);
+    assert_eq!(
+        outcome.recoveries,
+        [
+            Recovery::ToggledCodelChooser(CodelChooser::Right),
+            Recovery::RotatedPointer(Direction::Down),
+            Recovery::ToggledCodelChooser(CodelChooser::Left),
+            Recovery::RotatedPointer(Direction::Left),
+        ]
+    );
+    assert!(outcome.was_blocked());
+    assert_eq!(outcome.to.map(|to| to.position), Some((0, 0)));
+    assert_eq!(outcome.command, Some(Command::Pop));
+    assert!(!outcome.terminated);
+}
+
+#[test]
+fn programs_terminate_after_eight_failed_attempts() {
+    let mut program = program(&["RK", "KK"]);
+    let outcome = program.step_once().unwrap();
+    assert_eq!(
+        outcome.recoveries,
+        [
+            Recovery::ToggledCodelChooser(CodelChooser::Right),
+            Recovery::RotatedPointer(Direction::Down),
+            Recovery::ToggledCodelChooser(CodelChooser::Left),
+            Recovery::RotatedPointer(Direction::Left),
+            Recovery::ToggledCodelChooser(CodelChooser::Right),
+            Recovery::RotatedPointer(Direction::Up),
+            Recovery::ToggledCodelChooser(CodelChooser::Left),
+            Recovery::RotatedPointer(Direction::Right),
+        ]
+    );
+    assert!(outcome.terminated);
+    assert_eq!(outcome.from.position, (0, 0));
+    assert_eq!(outcome.to, None);
+    assert_eq!(outcome.command, None);
+    assert!(program.is_terminated());
+
+    // Once terminated, a step does nothing.
+    let outcome = program.step_once().unwrap();
+    assert!(outcome.terminated);
+    assert!(outcome.recoveries.is_empty());
+    assert_eq!(outcome.to, None);
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use common::grid;
+use piet_tool::trace::{TraceHeader, TRACE_VERSION};
+use piet_tool::{
+    load_image, ArithmeticMode, BufferIo, Command, PietProgram, TraceStep, TraceWriter,
+};
+
+// Runs an example for up to `max_steps` steps with a trace and returns the trace's lines.
+fn trace(
+    example: &str,
+    input: &str,
+    max_steps: i64,
+) -> (TraceHeader, Vec<TraceStep>, PietProgram<BufferIo>) {
+    let path = std::env::temp_dir().join(format!("piet_tool_trace_{}.jsonl", example));
+    let grid = load_image(&format!("examples/{}", example), 1).unwrap();
+    let mut program = PietProgram::with_io(grid, BufferIo::new(input));
+    program
+        .set_trace(Some(TraceWriter::create(&path).unwrap()))
+        .unwrap();
+    program.execute(max_steps).unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    let mut lines = text.lines();
+    let header = serde_json::from_str(lines.next().unwrap()).unwrap();
+    let steps = lines
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    (header, steps, program)
+}
+
+#[test]
+fn trace_has_a_line_per_step() {
+    let (header, steps, program) = trace("Piet_hello.png", "", -1);
+    assert_eq!(header.version, TRACE_VERSION);
+    assert_eq!(steps.len(), program.steps() as usize + 1);
+    for (number, step) in steps.iter().enumerate() {
+        assert_eq!(step.step, number as u64);
+        assert!(step.top.len() <= step.depth.min(5));
+    }
+    for pair in steps.windows(2) {
+        assert_eq!(pair[0].to, Some(pair[1].from));
+    }
+    assert_eq!(steps.last().unwrap().to, None);
+    let output = steps
+        .iter()
+        .filter_map(|step| step.output.as_deref())
+        .collect::<String>();
+    assert_eq!(output, program.io().output_string());
+}
+
+#[test]
+fn trace_records_input() {
+    let (_, steps, _) = trace("cowsay.png", "hi", 1000);
+    let input = steps
+        .iter()
+        .filter(|step| step.command == Some(Command::InChar))
+        .filter_map(|step| step.input.clone())
+        .collect::<Vec<String>>();
+    assert_eq!(input, ["104", "105"]);
+}
+
+#[test]
+fn trace_records_overflows() {
+    // Red to yellow is add, which overflows with the maximum value on the stack, and
+    // yellow to blue is greater, which has too few values to do anything.
+    let path =
+        std::env::temp_dir().join(format!("piet_tool_overflow_{}.jsonl", std::process::id()));
+    let mut program: PietProgram<BufferIo> =
+        PietProgram::with_io(grid(&["RYB"]), BufferIo::new(""));
+    program.set_arithmetic_mode(ArithmeticMode::Wrapping);
+    program.stack.push(i64::MAX);
+    program.stack.push(1);
+    program
+        .set_trace(Some(TraceWriter::create(&path).unwrap()))
+        .unwrap();
+    program.execute(2).unwrap();
+    assert_eq!(program.stack().values(), &[i64::MIN]);
+    let text = std::fs::read_to_string(&path).unwrap();
+    let lines = text.lines().skip(1).collect::<Vec<&str>>();
+    let steps = lines
+        .iter()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect::<Vec<TraceStep>>();
+    assert!(steps[0].overflowed);
+    assert!(lines[0].contains(r#""overflowed":true"#));
+    // Steps that didn't overflow leave the field out.
+    assert!(!steps[1].overflowed);
+    assert!(!lines[1].contains("overflowed"));
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use common::grid;
+use piet_tool::trace::{TraceReader, TraceSteps};
+use piet_tool::trace_diff::{compare, Comparison, Difference};
+use piet_tool::{BufferIo, Command, PietError, PietProgram, TraceWriter};
+
+// "RDRDW" pushes and pops forever, so most traces are cut off after `max_steps` steps.
+fn steps(rows: &[&str], max_steps: i64) -> TraceSteps<BufferIo> {
+    TraceSteps::new(
+        PietProgram::with_io(grid(rows), BufferIo::new("")),
+        max_steps,
+    )
+}
+
+#[test]
+fn identical_programs_have_identical_traces() {
+    let comparison = compare(
+        steps(&["RDRDW"], 10).map(Ok),
+        steps(&["RDRDW"], 10).map(Ok),
+        3,
+    )
+    .unwrap();
+    assert_eq!(comparison, Comparison::Identical(10));
+}
+
+#[test]
+fn divergence_is_reported_with_context() {
+    // Both push and pop, then one pushes again where the other adds.
+    let comparison = compare(
+        steps(&["RDRDW"], 10).map(Ok),
+        steps(&["RDRYW"], 10).map(Ok),
+        1,
+    )
+    .unwrap();
+    let divergence = match comparison {
+        Comparison::Diverged(divergence) => divergence,
+        other => panic!("expected a divergence, got {:?}", other),
+    };
+    assert_eq!(divergence.index, 2);
+    assert_eq!(
+        divergence.differences,
+        [Difference::Block, Difference::Command, Difference::Stack]
+    );
+    assert_eq!(divergence.before.len(), 1);
+    assert_eq!(divergence.before[0].command, Some(Command::Pop));
+    assert_eq!(divergence.a.len(), 2);
+    assert_eq!(divergence.a[0].command, Some(Command::Push));
+    assert_eq!(divergence.b[0].command, Some(Command::Add));
+}
+
+#[test]
+fn a_trace_that_ends_early_diverges() {
+    let comparison = compare(
+        steps(&["RDRDW"], 3).map(Ok),
+        steps(&["RDRDW"], 10).map(Ok),
+        3,
+    )
+    .unwrap();
+    match comparison {
+        Comparison::Diverged(divergence) => {
+            assert_eq!(divergence.index, 3);
+            assert_eq!(divergence.differences, [Difference::End]);
+            assert!(divergence.a.is_empty());
+            assert_eq!(divergence.b.len(), 4);
+        }
+        other => panic!("expected a divergence, got {:?}", other),
+    }
+}
+
+#[test]
+fn written_traces_read_back_as_the_live_trace() {
+    let path = std::env::temp_dir().join("piet_tool_trace_diff.jsonl");
+    let mut program: PietProgram<BufferIo> =
+        PietProgram::with_io(grid(&["RDDW", "KKKW"]), BufferIo::new(""));
+    program
+        .set_trace(Some(TraceWriter::create(&path).unwrap()))
+        .unwrap();
+    program.execute(-1).unwrap();
+    let written = TraceReader::open(&path).unwrap();
+    assert!(matches!(
+        compare(written, steps(&["RDDW", "KKKW"], -1).map(Ok), 3).unwrap(),
+        Comparison::Identical(_)
+    ));
+
+    std::fs::write(&path, "{\"version\":0,\"grid_hash\":\"\"}\n").unwrap();
+    assert!(matches!(TraceReader::open(&path), Err(PietError::Trace(_))));
+}
@@ -0,0 +1,338 @@
+mod common;
+
+use common::grid;
+use piet_tool::translator::{c, rust, wat, Translator};
+use piet_tool::{load_image, ArithmeticMode, BufferIo, Command, Grid, PietError, PietProgram};
+use std::path::Path;
+use std::process::Stdio;
+
+// Translates `grid` to Forth and returns the generated code.
+fn translate(grid: &Grid, name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("piet_tool_{}.f", name));
+    let mut translator = Translator::new(path.to_string_lossy().into_owned());
+    translator.translate(grid).unwrap();
+    translator.flush().unwrap();
+    std::fs::read_to_string(&path).unwrap()
+}
+
+#[test]
+fn pointer_and_switch_pick_the_next_state() {
+    // Red to dark cyan is a `Pointer` that leaves the DP pointing right, down, left or up
+    // in states 1 to 4.
+    let forth = translate(&grid(&["RC"]), "pointer");
+    assert!(forth.contains(
+        "0 OF PIET-POINTER PIET-DP @ CASE 0 OF 1 ENDOF 1 OF 2 ENDOF 2 OF 3 ENDOF 3 OF 4 ENDOF \
+         ENDCASE PIET-STATE ! ENDOF"
+    ));
+    // Light red to dark cyan is a `Switch`.
+    let forth = translate(&grid(&["LC"]), "switch");
+    assert!(forth.contains("0 OF PIET-SWITCH PIET-CC @ 0 = IF 1 ELSE 2 THEN PIET-STATE ! ENDOF"));
+}
+
+#[test]
+fn dp_and_cc_follow_the_way_out_of_a_block() {
+    // Dark red can only be left downwards into white, and the slide through it is turned
+    // back up into dark red by the edge and the black codel.
+    let forth = translate(&grid(&["RDK", "KWK"]), "recoveries");
+    assert!(forth.contains("1 OF 3 PIET-DP ! 1 PIET-CC ! 2 PIET-STATE ! ENDOF"));
+}
+
+#[test]
+fn every_command_is_defined_by_the_prelude() {
+    let mut translator = Translator::new(String::new());
+    for command in Command::EXECUTABLE {
+        translator.write(&command.to_forth(3).unwrap());
+    }
+    translator.check().unwrap();
+
+    translator.write("PIET_ROLL");
+    assert!(matches!(translator.check(), Err(PietError::Translation(_))));
+}
+
+#[test]
+fn every_example_translates() {
+    for entry in std::fs::read_dir("examples").unwrap() {
+        let path = entry.unwrap().path();
+        // ColorError.png is meant not to load.
+        if let Ok(grid) = load_image(&path.to_string_lossy(), 1) {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            translate(&grid, &name);
+            c::translate(&grid).unwrap();
+            rust::translate(&grid).unwrap();
+            // The module has to be valid WebAssembly too.
+            let wasm = ::wat::parse_str(wat::translate(&grid).unwrap()).unwrap();
+            wasmi::Module::new(&wasmi::Engine::default(), &wasm[..]).unwrap();
+        }
+    }
+}
+
+#[test]
+fn c_pointer_and_switch_pick_the_next_state() {
+    let code = c::translate(&grid(&["RC"])).unwrap();
+    assert!(code.contains(
+        "piet_pointer();\n            switch (dp) { case 0: state = 1; break; case 1: state = 2; \
+         break; case 2: state = 3; break; case 3: state = 4; break; }"
+    ));
+    let code = c::translate(&grid(&["LC"])).unwrap();
+    assert!(code.contains("piet_switch();\n            state = cc == 0 ? 1 : 2;"));
+}
+
+// Runs an example in the interpreter, with wrapping arithmetic like the generated code.
+fn interpret(example: &str, input: &str) -> Vec<u8> {
+    let grid = load_image(&format!("examples/{}", example), 1).unwrap();
+    let mut program = PietProgram::<_, i64>::with_io(grid, BufferIo::new(input));
+    program.set_arithmetic_mode(ArithmeticMode::Wrapping);
+    program.execute(-1).unwrap();
+    program.io().output().to_vec()
+}
+
+// Checks that `run` gives the interpreter's output on some examples. It is called with the
+// example, a name for its temporary files and a file holding its input: programs may stop
+// before reading all of their input, so it comes from a file rather than a pipe.
+fn assert_behaves_like_the_interpreter(run: impl Fn(&Grid, &str, &Path) -> Vec<u8>) {
+    let examples = [
+        ("Piet_hello.png", ""),
+        ("Add.png", "3 4\n"),
+        ("cowsay.png", "hi \u{e9}\n"),
+        ("99bottles.png", ""),
+        ("hanoi.gif", "3\n"),
+    ];
+    for (example, input) in examples {
+        let grid = load_image(&format!("examples/{}", example), 1).unwrap();
+        // Unique to the process, so that test runs at the same time don't share files.
+        let name = format!("{}_{}", example.replace('.', "_"), std::process::id());
+        let input_file = std::env::temp_dir().join(format!("piet_tool_{}.in", name));
+        std::fs::write(&input_file, input).unwrap();
+        assert_eq!(
+            run(&grid, &name, &input_file),
+            interpret(example, input),
+            "{}",
+            example
+        );
+    }
+}
+
+#[test]
+fn compiled_c_behaves_like_the_interpreter() {
+    assert_behaves_like_the_interpreter(|grid, name, input_file| {
+        let source = std::env::temp_dir().join(format!("piet_tool_{}.c", name));
+        let binary = std::env::temp_dir().join(format!("piet_tool_{}", name));
+        std::fs::write(&source, c::translate(grid).unwrap()).unwrap();
+        let status = std::process::Command::new("cc")
+            .args(["-std=c99", "-Wall", "-Wextra", "-pedantic", "-Werror", "-o"])
+            .arg(&binary)
+            .arg(&source)
+            .status()
+            .expect("needs a C compiler");
+        assert!(status.success(), "{} doesn't compile", name);
+        std::process::Command::new(&binary)
+            .stdin(Stdio::from(std::fs::File::open(input_file).unwrap()))
+            .output()
+            .unwrap()
+            .stdout
+    });
+}
+
+#[test]
+#[ignore = "needs gforth"]
+fn forth_in_gforth_behaves_like_the_interpreter() {
+    assert_behaves_like_the_interpreter(|grid, name, input_file| {
+        translate(grid, name);
+        let source = std::env::temp_dir().join(format!("piet_tool_{}.f", name));
+        let output = std::process::Command::new("gforth")
+            .arg(&source)
+            .args(["-e", "bye"])
+            .stdin(Stdio::from(std::fs::File::open(input_file).unwrap()))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{} doesn't run", name);
+        output.stdout
+    });
+}
+
+#[test]
+fn compiled_rust_behaves_like_the_interpreter() {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    // Shared by the examples and kept between runs, so that this crate and its
+    // dependencies are only built once.
+    let target = Path::new(env!("CARGO_TARGET_TMPDIR")).join("piet_tool_rust");
+    assert_behaves_like_the_interpreter(|grid, name, input_file| {
+        // A crate that runs the translation on stdin and stdout.
+        let package = format!("piet_tool_{}", name.to_lowercase());
+        let directory = std::env::temp_dir().join(format!("piet_tool_{}_crate", name));
+        std::fs::create_dir_all(directory.join("src")).unwrap();
+        std::fs::write(
+            directory.join("Cargo.toml"),
+            format!(
+                "[package]\nname = {:?}\nedition = \"2021\"\n\n[dependencies]\n\
+                 piet_tool = {{ path = {:?}, default-features = false }}\n",
+                package,
+                env!("CARGO_MANIFEST_DIR")
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("src/main.rs"),
+            "mod program;\n\nfn main() {\n    \
+             program::run(&mut piet_tool::StdIo::new()).unwrap();\n}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("src/program.rs"),
+            rust::translate(grid).unwrap(),
+        )
+        .unwrap();
+        // Built against the same versions of the dependencies as this crate.
+        std::fs::copy(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.lock"),
+            directory.join("Cargo.lock"),
+        )
+        .unwrap();
+        let status = std::process::Command::new(&cargo)
+            .args(["build", "--quiet", "--offline", "--manifest-path"])
+            .arg(directory.join("Cargo.toml"))
+            .env("CARGO_TARGET_DIR", &target)
+            .status()
+            .unwrap();
+        assert!(status.success(), "{} doesn't compile", name);
+        std::process::Command::new(target.join("debug").join(&package))
+            .stdin(Stdio::from(std::fs::File::open(input_file).unwrap()))
+            .output()
+            .unwrap()
+            .stdout
+    });
+}
+
+// The input and output of a WebAssembly translation, read and written the way the
+// interpreter does.
+struct Host {
+    input: Vec<u8>,
+    position: usize,
+    output: Vec<u8>,
+}
+
+impl Host {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek();
+        self.position += byte.is_some() as usize;
+        byte
+    }
+
+    fn getc(&mut self) -> i32 {
+        let first = match self.next() {
+            Some(byte) => byte,
+            None => return -1,
+        };
+        let len = match first {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        let mut bytes = vec![first];
+        while bytes.len() < len {
+            match self.next() {
+                Some(byte) => bytes.push(byte),
+                None => break,
+            }
+        }
+        match std::str::from_utf8(&bytes) {
+            Ok(s) => s.chars().next().unwrap() as i32,
+            Err(_) => first as i32,
+        }
+    }
+
+    fn getn(&mut self) -> (i32, i64) {
+        while self.peek().is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+        let mut text = match self.next() {
+            Some(byte) => (byte as char).to_string(),
+            None => return (0, 0),
+        };
+        while let Some(byte) = self.peek().filter(u8::is_ascii_digit) {
+            text.push(byte as char);
+            self.position += 1;
+        }
+        match text.parse() {
+            Ok(number) => (1, number),
+            Err(_) => (0, 0),
+        }
+    }
+}
+
+// Runs the WebAssembly translation of an example with `wasmi` and returns its output.
+fn run_wat(example: &str, input: &str) -> Vec<u8> {
+    use wasmi::{Caller, Engine, Linker, Module, Store};
+
+    let grid = load_image(&format!("examples/{}", example), 1).unwrap();
+    let wasm = ::wat::parse_str(wat::translate(&grid).unwrap()).unwrap();
+    let engine = Engine::default();
+    let module = Module::new(&engine, &wasm[..]).unwrap();
+    let host = Host {
+        input: input.as_bytes().to_vec(),
+        position: 0,
+        output: Vec::new(),
+    };
+    let mut store = Store::new(&engine, host);
+    let mut linker = Linker::<Host>::new(&engine);
+    linker
+        .func_wrap("env", "getc", |mut caller: Caller<'_, Host>| {
+            caller.data_mut().getc()
+        })
+        .unwrap();
+    linker
+        .func_wrap("env", "getn", |mut caller: Caller<'_, Host>| {
+            caller.data_mut().getn()
+        })
+        .unwrap();
+    linker
+        .func_wrap("env", "putc", |mut caller: Caller<'_, Host>, c: i32| {
+            let c = char::from_u32(c as u32).unwrap();
+            let mut buffer = [0; 4];
+            let bytes = c.encode_utf8(&mut buffer).as_bytes();
+            caller.data_mut().output.extend_from_slice(bytes);
+        })
+        .unwrap();
+    linker
+        .func_wrap("env", "putn", |mut caller: Caller<'_, Host>, n: i64| {
+            let text = n.to_string();
+            caller.data_mut().output.extend_from_slice(text.as_bytes());
+        })
+        .unwrap();
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .unwrap()
+        .start(&mut store)
+        .unwrap();
+    let run = instance.get_typed_func::<(), ()>(&store, "run").unwrap();
+    run.call(&mut store, ()).unwrap();
+    store.into_data().output
+}
+
+#[test]
+fn wat_behaves_like_the_interpreter() {
+    let examples = [
+        ("Piet_hello.png", ""),
+        ("Add.png", ""),
+        ("DivideByZero.png", ""),
+        ("cowsay.png", "hi \u{e9}\n"),
+        ("99bottles.png", ""),
+        ("hanoi.gif", "3\n"),
+        ("nprime-big.gif", "30\n"),
+        ("nprime-big.gif", "-5 x"),
+    ];
+    for (example, input) in examples {
+        assert_eq!(
+            run_wat(example, input),
+            interpret(example, input),
+            "{} with input {:?}",
+            example,
+            input
+        );
+    }
+}
@@ -0,0 +1,82 @@
+#![cfg(feature = "tui")]
+
+use piet_tool::tui::Viewport;
+
+fn view(origin: (i32, i32), zoom: i32) -> Viewport {
+    Viewport { origin, zoom }
+}
+
+#[test]
+fn every_half_cell_shows_one_codel_at_zoom_zero() {
+    let view = view((10, 20), 0);
+    assert_eq!(view.codel_size(), (1, 1));
+    assert_eq!(view.codel_at((0, 0)), (10, 20));
+    assert_eq!(view.codel_at((3, 5)), (13, 25));
+    assert_eq!(view.pixel_of((13, 25)), (3, 5));
+    assert_eq!(view.pixel_of((9, 20)), (-1, 0));
+}
+
+#[test]
+fn zooming_in_draws_codels_over_several_half_cells() {
+    let view = view((1, 1), 2);
+    assert_eq!(view.codel_size(), (4, 1));
+    assert_eq!(view.codel_at((3, 3)), (1, 1));
+    assert_eq!(view.codel_at((4, 9)), (2, 3));
+    // The middle of the four by four half-cells the codel is drawn in.
+    assert_eq!(view.pixel_of((2, 3)), (6, 10));
+    for x in -3..10 {
+        for y in -3..10 {
+            assert_eq!(view.codel_at(view.pixel_of((x, y))), (x, y));
+        }
+    }
+}
+
+#[test]
+fn zooming_out_skips_codels() {
+    let view = view((0, 0), -2);
+    assert_eq!(view.codel_size(), (1, 4));
+    assert_eq!(view.codel_at((1, 2)), (4, 8));
+    // Every codel of a four by four square is shown in the same half-cell.
+    assert_eq!(view.pixel_of((4, 8)), (1, 2));
+    assert_eq!(view.pixel_of((7, 11)), (1, 2));
+    assert_eq!(view.pixel_of((-1, 0)), (-1, 0));
+}
+
+#[test]
+fn zoom_is_clamped() {
+    let mut view = Viewport::default();
+    for _ in 0..10 {
+        view.zoom_in();
+    }
+    assert_eq!(view.zoom, 4);
+    for _ in 0..20 {
+        view.zoom_out();
+    }
+    assert_eq!(view.zoom, -5);
+}
+
+#[test]
+fn panning_moves_by_sixteen_half_cells() {
+    for (zoom, distance) in [(0, 16), (2, 4), (4, 1), (-1, 32), (-5, 512)] {
+        let mut panned = view((5, 5), zoom);
+        panned.pan(1, 0);
+        assert_eq!(panned.origin, (5 + distance, 5), "zoom {}", zoom);
+        panned.pan(-1, -1);
+        assert_eq!(panned.origin, (5, 5 - distance), "zoom {}", zoom);
+    }
+}
+
+#[test]
+fn keeping_in_view_centers_a_codel_out_of_sight() {
+    let mut near = view((0, 0), 0);
+    near.keep_in_view((5, 5), (20, 10));
+    assert_eq!(near.origin, (0, 0));
+
+    near.keep_in_view((100, 3), (20, 10));
+    assert_eq!(near.origin, (90, -2));
+    assert_eq!(near.pixel_of((100, 3)), (10, 5));
+
+    let mut zoomed = view((0, 0), 1);
+    zoomed.keep_in_view((-7, 40), (20, 10));
+    assert_eq!(zoomed.pixel_of((-7, 40)), (11, 5));
+}
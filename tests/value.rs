@@ -0,0 +1,97 @@
+use piet_tool::value::Operation::{Add, Divide, Multiply, Remainder, Subtract};
+use piet_tool::PietValue;
+
+#[test]
+fn i32_overflows_at_its_own_bounds() {
+    assert_eq!(i32::MAX.checked(Add, &1), None);
+    assert_eq!(i32::MIN.checked(Subtract, &1), None);
+    assert_eq!(i32::MIN.checked(Divide, &-1), None);
+    assert_eq!(7.checked(Remainder, &-3), Some(1));
+    assert_eq!(i32::MAX.wrapping(Add, &1), i32::MIN);
+    assert_eq!(i32::MAX.wrapping(Multiply, &2), -2);
+    assert_eq!(i32::MIN.wrapping(Divide, &-1), i32::MIN);
+    assert_eq!(i32::MAX.saturating(Multiply, &2), i32::MAX);
+    assert_eq!(i32::MIN.saturating(Subtract, &1), i32::MIN);
+    assert_eq!(i32::MIN.saturating(Remainder, &-1), 0);
+    assert_eq!(i32::from_i64(i64::from(i32::MAX) + 1), None);
+}
+
+#[test]
+fn i128_holds_more_than_i64() {
+    let past_i64 = i128::from(i64::MAX) + 1;
+    assert_eq!(
+        i128::from(i64::MAX).checked(Add, &1).unwrap().to_i64(),
+        None
+    );
+    assert_eq!(
+        i128::from(i64::MAX).checked(Multiply, &2),
+        Some(2 * i128::from(i64::MAX))
+    );
+    assert_eq!(i128::MAX.checked(Add, &1), None);
+    assert_eq!(i128::MAX.wrapping(Add, &1), i128::MIN);
+    assert_eq!(i128::MIN.wrapping(Divide, &-1), i128::MIN);
+    assert_eq!(i128::MAX.saturating(Multiply, &past_i64), i128::MAX);
+    assert_eq!(i128::MIN.saturating(Remainder, &-1), 0);
+}
+
+#[test]
+fn rem_euclid_is_never_negative() {
+    assert_eq!((-1i32).rem_euclid_u64(4), 3);
+    assert_eq!(i32::MIN.rem_euclid_u64(3), 1);
+    assert_eq!((-1i128).rem_euclid_u64(u64::MAX), u64::MAX - 1);
+    assert_eq!(i128::MAX.rem_euclid_u64(1 << 63), (1 << 63) - 1);
+    assert_eq!(i128::MIN.rem_euclid_u64(10), 2);
+}
+
+#[cfg(feature = "bigint")]
+mod bigint {
+    use num_bigint::BigInt;
+    use piet_tool::value::Operation::{Add, Divide, Multiply, Remainder, Subtract};
+    use piet_tool::PietValue;
+
+    fn big(value: &str) -> BigInt {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn bigint_never_overflows() {
+        let max = BigInt::from(i64::MAX);
+        let one = BigInt::from(1);
+        let past_i64 = big("9223372036854775808");
+        assert_eq!(max.checked(Add, &one), Some(past_i64.clone()));
+        assert_eq!(past_i64.to_i64(), None);
+        assert_eq!(
+            max.checked(Multiply, &max),
+            Some(big("85070591730234615847396907784232501249"))
+        );
+        assert_eq!(
+            BigInt::from(i64::MIN).checked(Subtract, &one).unwrap(),
+            -&past_i64 - 1
+        );
+        // Wrapping and saturating give the exact result too.
+        assert_eq!(max.wrapping(Add, &one), past_i64);
+        assert_eq!(max.saturating(Add, &one), past_i64);
+        // Division rounds towards zero, like the fixed-width types.
+        assert_eq!(
+            BigInt::from(-7).checked(Divide, &BigInt::from(2)),
+            Some(BigInt::from(-3))
+        );
+        assert_eq!(
+            BigInt::from(-7).checked(Remainder, &BigInt::from(2)),
+            Some(BigInt::from(-1))
+        );
+    }
+
+    #[test]
+    fn bigint_rem_euclid_is_never_negative() {
+        assert_eq!(BigInt::from(-1).rem_euclid_u64(4), 3);
+        assert_eq!(
+            big("-18446744073709551617").rem_euclid_u64(u64::MAX),
+            u64::MAX - 2
+        );
+        assert_eq!(
+            big("340282366920938463463374607431768211456").rem_euclid_u64(7),
+            4
+        );
+    }
+}
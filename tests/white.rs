@@ -0,0 +1,74 @@
+mod common;
+
+use common::grid;
+use piet_tool::{BufferIo, CodelChooser, Direction, PietProgram};
+
+fn program(rows: &[&str]) -> PietProgram<BufferIo> {
+    PietProgram::with_io(grid(rows), BufferIo::new(""))
+}
+
+#[test]
+fn direct_color_change_executes_a_command() {
+    let mut program = program(&["RRD"]);
+    let outcome = program.step_once().unwrap();
+    assert!(!outcome.crossed_white);
+    assert_eq!(program.stack().values(), &[2]);
+}
+
+#[test]
+fn sliding_through_white_executes_no_command() {
+    let mut program = program(&["RRWWD"]);
+    let outcome = program.step_once().unwrap();
+    assert!(outcome.crossed_white);
+    assert_eq!(outcome.command, None);
+    assert_eq!(outcome.to.unwrap().position, (4, 0));
+    assert!(program.stack().is_empty());
+}
+
+#[test]
+fn slide_into_edge_toggles_cc_and_rotates_dp() {
+    let mut program = program(&["RW", "KB"]);
+    let outcome = program.step_once().unwrap();
+    assert!(outcome.crossed_white);
+    assert!(outcome.was_blocked());
+    assert_eq!(outcome.to.unwrap().position, (1, 1));
+    assert_eq!(program.direction_pointer(), Direction::Down);
+    assert_eq!(program.codel_chooser(), CodelChooser::Right);
+}
+
+#[test]
+fn slide_into_black_toggles_cc_and_rotates_dp() {
+    let mut program = program(&["RWK", "KBK"]);
+    let outcome = program.step_once().unwrap();
+    assert_eq!(outcome.to.unwrap().position, (1, 1));
+    assert_eq!(program.direction_pointer(), Direction::Down);
+    assert_eq!(program.codel_chooser(), CodelChooser::Right);
+}
+
+#[test]
+fn revisiting_a_white_path_terminates() {
+    let mut program = program(&["RW", "KW"]);
+    let outcome = program.step_once().unwrap();
+    assert!(outcome.crossed_white);
+    assert!(outcome.terminated);
+    assert_eq!(outcome.to, None);
+    assert!(program.is_terminated());
+}
+
+#[test]
+fn program_starting_on_white_slides_to_first_block() {
+    let mut program = program(&["WWRD"]);
+    let outcome = program.step_once().unwrap();
+    assert!(outcome.crossed_white);
+    assert_eq!(outcome.to.unwrap().position, (2, 0));
+    program.step_once().unwrap();
+    assert_eq!(program.stack().values(), &[1]);
+}
+
+#[test]
+fn all_white_program_terminates() {
+    let mut program = program(&["WW", "WW"]);
+    program.execute(100).unwrap();
+    assert!(program.is_terminated());
+    assert_eq!(program.io().output(), b"");
+}